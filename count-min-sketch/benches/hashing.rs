@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cm_sketch::{CountMinSketch, HashStrategy};
+
+// The previous row indexing, which hashed the whole value once per row with the row as the seed
+fn per_row_update(data: &mut [u64], width: usize, depth: usize, strategy: HashStrategy, value: &String) {
+    for i in 0..depth {
+        let column = strategy.hash_with_seed(value, i as u64) % width as u64;
+        data[column as usize + (i * width)] += 1;
+    }
+}
+
+fn keys(length: usize) -> Vec<String> {
+    (0..1000).map(|i| format!("{:0>width$}", i, width = length)).collect()
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    for length in [8, 64, 512] {
+        let keys = keys(length);
+        group.throughput(Throughput::Elements(keys.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("single_hash", length), &keys, |b, keys| {
+            let mut sketch = CountMinSketch::new(0.001, 0.001).unwrap();
+            b.iter(|| sketch.update_batch(black_box(keys)));
+        });
+
+        group.bench_with_input(BenchmarkId::new("per_row_hash", length), &keys, |b, keys| {
+            let sketch = CountMinSketch::new(0.001, 0.001).unwrap();
+            let (width, depth) = (sketch.width(), sketch.depth());
+            let mut data = vec![0u64; width * depth];
+            b.iter(|| {
+                for key in black_box(keys) {
+                    per_row_update(&mut data, width, depth, HashStrategy::XxHash, key);
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn estimate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("estimate");
+    for length in [8, 64, 512] {
+        let keys = keys(length);
+        let mut sketch = CountMinSketch::new(0.001, 0.001).unwrap();
+        sketch.update_batch(&keys);
+        group.throughput(Throughput::Elements(keys.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("estimate_batch", length), &keys, |b, keys| {
+            b.iter(|| sketch.estimate_batch(black_box(keys)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update, estimate);
+criterion_main!(benches);
@@ -0,0 +1,148 @@
+use std::marker::PhantomData;
+
+use crate::counter::Counter;
+use crate::{cell_count, depth_for, dimensions, guarantees, CountMinError, CountMinSketch, HashStrategy, UpdateMode};
+
+#[derive(Clone, Copy, Debug)]
+enum Size {
+    ErrorRate { epsilon: f64, delta: f64 },
+    Dimensions { width: usize, depth: usize },
+    MemoryBudget { bytes: usize, delta: f64 },
+}
+
+/// Builds a count-min-sketch, every input is validated on build
+/// The size is given in one of three ways, the last one given is used:
+/// error rate and confidence, exact dimensions or a memory budget with a confidence
+/// Counters are u64 unless another counter type is chosen
+///
+/// ```
+/// use cm_sketch::{CountMinSketch, UpdateMode};
+///
+/// let sketch = CountMinSketch::builder()
+///     .memory_budget(64 * 1024, 0.01)
+///     .mode(UpdateMode::Conservative)
+///     .build()
+///     .unwrap();
+/// println!("error rate: {}, confidence: {}", sketch.epsilon(), 1.0 - sketch.delta());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CountMinSketchBuilder<C: Counter = u64> {
+    size: Option<Size>,
+    mode: UpdateMode,
+    hash_strategy: HashStrategy,
+    seed: u64,
+    reset_interval: Option<u64>,
+    counter: PhantomData<C>,
+}
+
+impl<C: Counter> CountMinSketchBuilder<C> {
+    /// Sizes the sketch for the error rate epsilon with 1 - delta confidence
+    pub fn error_rate(mut self, epsilon: f64, delta: f64) -> Self {
+        self.size = Some(Size::ErrorRate { epsilon, delta });
+        self
+    }
+
+    /// Sizes the sketch with the exact number of columns and rows
+    pub fn dimensions(mut self, width: usize, depth: usize) -> Self {
+        self.size = Some(Size::Dimensions { width, depth });
+        self
+    }
+
+    /// Sizes the sketch to the bytes of counters, delta decides the depth
+    pub fn memory_budget(mut self, bytes: usize, delta: f64) -> Self {
+        self.size = Some(Size::MemoryBudget { bytes, delta });
+        self
+    }
+
+    pub fn mode(mut self, mode: UpdateMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn hash_strategy(mut self, hash_strategy: HashStrategy) -> Self {
+        self.hash_strategy = hash_strategy;
+        self
+    }
+
+    /// Seed of the hash function, sketches can only be merged if they have the same seed
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Halves every counter after the given number of updates, see CountMinSketch::set_reset_interval
+    pub fn reset_after(mut self, updates: u64) -> Self {
+        self.reset_interval = Some(updates);
+        self
+    }
+
+    /// Chooses the counter type, for instance u8 for the sketches of the low volume streams
+    pub fn counter<D: Counter>(self) -> CountMinSketchBuilder<D> {
+        CountMinSketchBuilder {
+            size: self.size,
+            mode: self.mode,
+            hash_strategy: self.hash_strategy,
+            seed: self.seed,
+            reset_interval: self.reset_interval,
+            counter: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> Result<CountMinSketch<C>, CountMinError> {
+        let (width, depth, epsilon, delta) = match self.size.ok_or(CountMinError::MissingSize)? {
+            Size::ErrorRate { epsilon, delta } => {
+                let (width, depth) = dimensions(epsilon, delta)?;
+                (width, depth, epsilon, delta)
+            },
+            Size::Dimensions { width, depth } => {
+                if width == 0 || depth == 0 { return Err(CountMinError::WrongDimensions); }
+                cell_count(width, depth, std::mem::size_of::<C>())?;
+                let (epsilon, delta) = guarantees(width, depth);
+                (width, depth, epsilon, delta)
+            },
+            Size::MemoryBudget { bytes, delta } => {
+                let depth = depth_for(delta)?;
+                let width = bytes / (depth * std::mem::size_of::<C>());
+                if width == 0 { return Err(CountMinError::InsufficientMemory(bytes)); }
+                let (epsilon, _) = guarantees(width, depth);
+                (width, depth, epsilon, delta)
+            },
+        };
+        let mut sketch = CountMinSketch::from_parts(width, depth, epsilon, delta, self.mode, self.hash_strategy);
+        sketch.seed = self.seed;
+        sketch.set_reset_interval(self.reset_interval);
+        Ok(sketch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_with_options() {
+        let sketch = CountMinSketch::builder()
+            .error_rate(0.1, 0.1)
+            .mode(UpdateMode::Conservative)
+            .hash_strategy(HashStrategy::Fnv)
+            .build()
+            .unwrap();
+        assert_eq!(28, sketch.width());
+        assert_eq!(3, sketch.depth());
+        assert_eq!(UpdateMode::Conservative, sketch.mode());
+        assert_eq!(HashStrategy::Fnv, sketch.hash_strategy());
+    }
+
+    #[test]
+    fn last_size_wins() {
+        let sketch = CountMinSketch::builder().error_rate(0.1, 0.1).dimensions(10, 2).build().unwrap();
+        assert_eq!(10, sketch.width());
+        assert_eq!(2, sketch.depth());
+    }
+
+    #[test]
+    fn size_is_required() {
+        assert!(matches!(CountMinSketch::builder().build(), Err(CountMinError::MissingSize)));
+        assert!(matches!(CountMinSketch::builder().error_rate(0.0, 0.1).build(), Err(CountMinError::WrongInput)));
+    }
+}
@@ -0,0 +1,177 @@
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{dimensions, row_indexes, CountMinError, CountMinSketch, HashStrategy, UpdateMode};
+
+/// Count-min-sketch which can be updated from many threads at the same time without a lock
+/// Counters are atomic, so update only needs a shared reference.
+/// Counters only grow, thus an estimate taken while other threads are updating
+/// is somewhere between the estimates before and after those updates.
+/// Only the standard update mode is supported, a conservative update reads the row minimum before raising it,
+/// two threads reading the same minimum would lose one of their increments
+#[derive(Debug)]
+pub struct AtomicCountMinSketch {
+    data: Vec<AtomicU64>,
+    depth: usize,
+    width: usize,
+    epsilon: f64,
+    delta: f64,
+    hash_strategy: HashStrategy,
+    seed: u64,
+    total: AtomicU64,
+}
+
+impl AtomicCountMinSketch {
+    /// Creates a new concurrent count-min-sketch, see CountMinSketch::new
+    pub fn new(epsilon: f64, delta: f64) -> Result<Self, CountMinError> {
+        Self::with_options(epsilon, delta, UpdateMode::Standard, HashStrategy::default())
+    }
+
+    /// Creates a new concurrent count-min-sketch with the given update mode and hash strategy
+    /// UpdateMode::Conservative is rejected
+    pub fn with_options(epsilon: f64, delta: f64, mode: UpdateMode, hash_strategy: HashStrategy) -> Result<Self, CountMinError> {
+        if mode != UpdateMode::Standard { return Err(CountMinError::StandardModeRequired("concurrent sketch")); }
+        let (width, depth) = dimensions(epsilon, delta)?;
+        Ok(Self {
+            data: (0..width * depth).map(|_| AtomicU64::new(0)).collect(),
+            depth,
+            width,
+            epsilon,
+            delta,
+            hash_strategy,
+            seed: 0,
+            total: AtomicU64::new(0),
+        })
+    }
+
+    /// Creates a concurrent sketch with the same configuration and counters
+    /// Sketches in conservative update mode are rejected
+    pub fn from_sketch(sketch: &CountMinSketch) -> Result<Self, CountMinError> {
+        if sketch.mode != UpdateMode::Standard { return Err(CountMinError::StandardModeRequired("concurrent sketch")); }
+        Ok(Self {
+            data: sketch.data.iter().map(|cell| AtomicU64::new(*cell)).collect(),
+            depth: sketch.depth,
+            width: sketch.width,
+            epsilon: sketch.epsilon,
+            delta: sketch.delta,
+            hash_strategy: sketch.hash_strategy,
+            seed: sketch.seed,
+            total: AtomicU64::new(sketch.total),
+        })
+    }
+
+    pub fn update<T>(&self, value: T, frequency: Option<u64>) where T : Hash {
+        let frequency = frequency.unwrap_or(1);
+        saturating_fetch_add(&self.total, frequency);
+        for index in self.indexes(&value) {
+            saturating_fetch_add(&self.data[index], frequency);
+        }
+    }
+
+    pub fn estimate<T>(&self, value: T) -> u64 where T : Hash {
+        self.indexes(&value)
+            .map(|index| self.data[index].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Copies the counters into a plain sketch
+    /// Updates which are running at the same time may be partially included
+    pub fn snapshot(&self) -> CountMinSketch {
+        let mut sketch = CountMinSketch::from_parts(self.width, self.depth, self.epsilon, self.delta, UpdateMode::Standard, self.hash_strategy);
+        sketch.data = self.data.iter().map(|cell| cell.load(Ordering::Relaxed)).collect();
+        sketch.seed = self.seed;
+        sketch.total = self.total.load(Ordering::Relaxed);
+        sketch
+    }
+
+    fn indexes<T>(&self, value: &T) -> impl Iterator<Item = usize> where T : Hash {
+        row_indexes(self.hash_strategy.hash_with_seed(value, self.seed), self.width, self.depth)
+    }
+}
+
+// counters stay at u64::MAX like the plain sketch's, fetch_add would wrap around and underestimate
+fn saturating_fetch_add(cell: &AtomicU64, frequency: u64) {
+    _ = cell.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| Some(count.saturating_add(frequency)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn updates_from_many_threads() {
+        let sketch = AtomicCountMinSketch::new(0.01, 0.01).unwrap();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for i in 0..1000u64 {
+                        sketch.update(i % 10, None);
+                    }
+                });
+            }
+        });
+        for i in 0..10u64 {
+            assert_eq!(400, sketch.estimate(i));
+        }
+    }
+
+    #[test]
+    fn snapshot_matches_plain_sketch() {
+        let sketch = AtomicCountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::Fnv).unwrap();
+        let mut plain = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::Fnv).unwrap();
+        for i in 0..50u64 {
+            sketch.update(i % 7, Some(i));
+            plain.update(i % 7, Some(i));
+        }
+
+        let snapshot = sketch.snapshot();
+        for i in 0..7u64 {
+            assert_eq!(plain.estimate(i), snapshot.estimate(i));
+        }
+        assert!(plain.merge(&snapshot).is_ok());
+    }
+
+    #[test]
+    fn standard_counters_saturate() {
+        let sketch = AtomicCountMinSketch::new(0.1, 0.1).unwrap();
+        sketch.update(1, Some(u64::MAX));
+        sketch.update(1, Some(2));
+        assert_eq!(u64::MAX, sketch.estimate(1));
+        assert_eq!(u64::MAX, sketch.snapshot().total_count());
+    }
+
+    #[test]
+    fn contended_key_loses_no_updates() {
+        let sketch = AtomicCountMinSketch::new(0.1, 0.1).unwrap();
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100_000 {
+                        sketch.update(1u64, None);
+                    }
+                });
+            }
+        });
+        assert_eq!(800_000, sketch.estimate(1u64));
+    }
+
+    #[test]
+    fn conservative_mode_is_rejected() {
+        let result = AtomicCountMinSketch::with_options(0.1, 0.1, UpdateMode::Conservative, HashStrategy::default());
+        assert!(matches!(result, Err(CountMinError::StandardModeRequired(_))));
+
+        let plain = CountMinSketch::with_mode(0.1, 0.1, UpdateMode::Conservative).unwrap();
+        assert!(matches!(AtomicCountMinSketch::from_sketch(&plain), Err(CountMinError::StandardModeRequired(_))));
+    }
+
+    #[test]
+    fn from_sketch_keeps_counters() {
+        let mut plain = CountMinSketch::new(0.1, 0.1).unwrap();
+        plain.update("a", Some(3));
+        let sketch = AtomicCountMinSketch::from_sketch(&plain).unwrap();
+        sketch.update("a", None);
+        assert_eq!(4, sketch.estimate("a"));
+    }
+}
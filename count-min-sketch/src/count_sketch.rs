@@ -0,0 +1,106 @@
+use std::hash::Hash;
+
+use crate::{dimensions, median, row_indexes, CountMinError, HashStrategy};
+
+/// Count sketch, a signed relative of count-min-sketch
+/// Every row also hashes the value into a sign, so the collisions cancel each other out on average
+/// Counters are signed, thus deletions and negative deltas are allowed (turnstile streams)
+/// The estimate is the median of the rows instead of the minimum
+#[derive(Clone, Debug)]
+pub struct CountSketch {
+    data: Vec<i64>,
+    depth: usize,
+    width: usize,
+    hash_strategy: HashStrategy,
+}
+
+impl CountSketch {
+    /// Creates a new count sketch
+    /// epsilon and delta have the same meaning as in CountMinSketch::new
+    pub fn new(epsilon: f64, delta: f64) -> Result<Self, CountMinError> {
+        Self::with_hash_strategy(epsilon, delta, HashStrategy::default())
+    }
+
+    /// Creates a new count sketch with the given hash strategy
+    pub fn with_hash_strategy(epsilon: f64, delta: f64, hash_strategy: HashStrategy) -> Result<Self, CountMinError> {
+        let (width, depth) = dimensions(epsilon, delta)?;
+        Ok(Self {
+            data: vec![0; width * depth],
+            depth,
+            width,
+            hash_strategy,
+        })
+    }
+
+    /// Adds the delta to the value's frequency, delta can be negative
+    /// Counters saturate at i64::MIN and i64::MAX instead of overflowing
+    pub fn update<T>(&mut self, value: T, delta: i64) where T : Hash {
+        let hash = self.hash_strategy.hash_value(&value);
+        for (row, index) in row_indexes(hash, self.width, self.depth).enumerate() {
+            self.data[index] = self.data[index].saturating_add(Self::sign(hash, row).saturating_mul(delta));
+        }
+    }
+
+    /// Estimated frequency of the value, it can be both over and under the real one
+    pub fn estimate<T>(&self, value: T) -> i64 where T : Hash {
+        let hash = self.hash_strategy.hash_value(&value);
+        let mut counts: Vec<i64> = row_indexes(hash, self.width, self.depth).enumerate()
+            .map(|(row, index)| Self::sign(hash, row).saturating_mul(self.data[index]))
+            .collect();
+        median(&mut counts)
+    }
+
+    // the hash is remixed with the row (splitmix64), so the sign is independent from the column
+    fn sign(hash: u64, row: usize) -> i64 {
+        let mut mixed = hash.wrapping_add((row as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        mixed ^= mixed >> 31;
+        if mixed >> 63 == 0 { 1 } else { -1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_value_is_exact() {
+        let mut sketch = CountSketch::new(0.1, 0.1).unwrap();
+        sketch.update(5, 3);
+        assert_eq!(3, sketch.estimate(5));
+    }
+
+    #[test]
+    fn deletions_are_subtracted() {
+        let mut sketch = CountSketch::new(0.1, 0.01).unwrap();
+        sketch.update("a", 10);
+        sketch.update("b", 4);
+        sketch.update("a", -7);
+        sketch.update("b", -4);
+        assert_eq!(3, sketch.estimate("a"));
+        assert_eq!(0, sketch.estimate("b"));
+    }
+
+    #[test]
+    fn negative_frequencies_are_estimated() {
+        let mut sketch = CountSketch::new(0.1, 0.01).unwrap();
+        sketch.update(1, -5);
+        assert_eq!(-5, sketch.estimate(1));
+    }
+
+    #[test]
+    fn extreme_deltas_saturate() {
+        let mut sketch = CountSketch::new(0.1, 0.02).unwrap();
+        sketch.update(1, i64::MIN);
+        assert!(sketch.estimate(1) <= -i64::MAX);
+        sketch.update(2, i64::MAX);
+        sketch.update(2, i64::MAX);
+        assert_eq!(i64::MAX, sketch.estimate(2));
+    }
+
+    #[test]
+    fn wrong_input_is_rejected() {
+        assert!(matches!(CountSketch::new(1.5, 0.1), Err(CountMinError::WrongInput)));
+    }
+}
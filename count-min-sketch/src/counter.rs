@@ -0,0 +1,54 @@
+use std::fmt::Debug;
+
+/// Cell type of a count-min-sketch
+/// Smaller counters save memory, a counter which reaches its max value stays there instead of overflowing
+pub trait Counter: Copy + Ord + Default + Debug {
+    const MAX: Self;
+    /// Size of the counter in snapshots
+    const BYTES: usize;
+
+    /// Converts the value, saturating at MAX
+    fn from_u64(value: u64) -> Self;
+
+    fn to_u64(self) -> u64;
+
+    fn saturating_add(self, other: Self) -> Self;
+
+    fn write_le(self, output: &mut Vec<u8>);
+
+    /// Reads a counter from exactly BYTES little endian bytes
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_counter {
+    ($($counter:ty),*) => {
+        $(
+            impl Counter for $counter {
+                const MAX: Self = <$counter>::MAX;
+                const BYTES: usize = std::mem::size_of::<$counter>();
+
+                fn from_u64(value: u64) -> Self {
+                    value.try_into().unwrap_or(Self::MAX)
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$counter>::saturating_add(self, other)
+                }
+
+                fn write_le(self, output: &mut Vec<u8>) {
+                    output.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    <$counter>::from_le_bytes(bytes.try_into().expect("counter bytes have the right length"))
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(u8, u16, u32, u64);
@@ -0,0 +1,72 @@
+use std::hash::Hash;
+
+use crate::{median, row_indexes, HashStrategy};
+
+/// Cell by cell difference of two count-min-sketches, created by CountMinSketch::subtract
+/// Counters are signed, the collisions of the both sketches may cancel or add up,
+/// so the median of the rows is used instead of the minimum
+#[derive(Clone, Debug)]
+pub struct SketchDifference {
+    data: Vec<i64>,
+    width: usize,
+    depth: usize,
+    hash_strategy: HashStrategy,
+    seed: u64,
+}
+
+impl SketchDifference {
+    pub(crate) fn new(data: Vec<i64>, width: usize, depth: usize, hash_strategy: HashStrategy, seed: u64) -> Self {
+        Self { data, width, depth, hash_strategy, seed }
+    }
+
+    /// Estimated change of the value's frequency, positive if it increased
+    pub fn change_estimate<T>(&self, value: T) -> i64 where T : Hash {
+        let hash = self.hash_strategy.hash_with_seed(&value, self.seed);
+        let mut changes: Vec<i64> = row_indexes(hash, self.width, self.depth)
+            .map(|index| self.data[index])
+            .collect();
+        median(&mut changes)
+    }
+
+    /// Values whose absolute change is at least the threshold, with their changes
+    /// The sketch doesn't keep the values, so the candidates must be given
+    pub fn changed<'a, T>(&self, candidates: &'a [T], threshold: u64) -> Vec<(&'a T, i64)> where T : Hash {
+        candidates.iter()
+            .map(|value| (value, self.change_estimate(value)))
+            .filter(|(_, change)| change.unsigned_abs() >= threshold)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CountMinError, CountMinSketch};
+
+    #[test]
+    fn change_between_windows() {
+        let mut previous = CountMinSketch::new(0.01, 0.01).unwrap();
+        let mut current = CountMinSketch::new(0.01, 0.01).unwrap();
+        for i in 0..100u64 {
+            previous.update(i, Some(5));
+            current.update(i, Some(5));
+        }
+        previous.update("spike", Some(2));
+        current.update("spike", Some(50));
+        previous.update("drop", Some(30));
+
+        let difference = current.subtract(&previous).unwrap();
+        assert_eq!(48, difference.change_estimate("spike"));
+        assert_eq!(-30, difference.change_estimate("drop"));
+        assert_eq!(0, difference.change_estimate(7u64));
+
+        let candidates = ["spike", "drop", "other"];
+        assert_eq!(vec![(&"spike", 48), (&"drop", -30)], difference.changed(&candidates, 10));
+    }
+
+    #[test]
+    fn subtract_rejects_incompatible_sketches() {
+        let first = CountMinSketch::new(0.1, 0.1).unwrap();
+        let second = CountMinSketch::new(0.2, 0.1).unwrap();
+        assert!(matches!(first.subtract(&second), Err(CountMinError::DimensionMismatch(..))));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{CountMinError, CountMinSketch};
+
+/// Count-min-sketches over dyadic ranges of integer keys, for range sums and quantiles
+/// Level 0 counts the keys themselves, level l counts the ranges of size 2^l, [k * 2^l, (k + 1) * 2^l)
+/// Any range is the union of at most 2 * log2(universe) dyadic ranges, so a range estimate
+/// is the sum of that many point estimates. Keys must be smaller than the universe size
+#[derive(Clone, Debug)]
+pub struct DyadicCountMinSketch {
+    levels: Vec<CountMinSketch>,
+    universe: u64,
+    total: u64,
+}
+
+impl DyadicCountMinSketch {
+    /// Creates a new dyadic sketch for the keys in 0..universe
+    /// epsilon and delta are passed to every level's sketch
+    pub fn new(epsilon: f64, delta: f64, universe: u64) -> Result<Self, CountMinError> {
+        if universe == 0 || universe > 1 << 63 { return Err(CountMinError::WrongUniverse); }
+        // enough levels for the top one to be a single range covering the universe
+        let level_count = (u64::BITS - (universe - 1).leading_zeros()) as usize + 1;
+        Ok(Self {
+            levels: vec![CountMinSketch::new(epsilon, delta)?; level_count],
+            universe,
+            total: 0,
+        })
+    }
+
+    /// Size of the key universe
+    pub fn universe(&self) -> u64 {
+        self.universe
+    }
+
+    /// Updates the key on every level, keys out of the universe are ignored
+    pub fn update(&mut self, key: u64, frequency: Option<u64>) {
+        if key >= self.universe { return; }
+        let frequency = frequency.unwrap_or(1);
+        for (level, sketch) in self.levels.iter_mut().enumerate() {
+            sketch.update(key >> level, Some(frequency));
+        }
+        self.total = self.total.saturating_add(frequency);
+    }
+
+    /// Estimated frequency of a single key
+    pub fn estimate(&self, key: u64) -> u64 {
+        if key >= self.universe { return 0; }
+        self.levels[0].estimate(key)
+    }
+
+    /// Estimated total frequency of the keys in lo..=hi
+    pub fn range_estimate(&self, lo: u64, hi: u64) -> u64 {
+        let hi = hi.min(self.universe - 1);
+        if lo > hi { return 0; }
+
+        // greedily takes the biggest dyadic range starting at lo which still fits in the range
+        let mut sum = 0;
+        let mut start = lo;
+        let top = self.levels.len() - 1;
+        loop {
+            let mut level = (start.trailing_zeros() as usize).min(top);
+            while level > 0 && start + ((1u64 << level) - 1) > hi {
+                level -= 1;
+            }
+            sum = u64::saturating_add(sum, self.levels[level].estimate(start >> level));
+
+            let end = start + ((1u64 << level) - 1);
+            if end >= hi { break; }
+            start = end + 1;
+        }
+        sum
+    }
+
+    /// Smallest key whose estimated rank reaches q of the stream, q must be between 0 and 1
+    /// Walks down from the top level, choosing the left or right half by its estimated weight
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        if self.total == 0 || !(0.0..=1.0).contains(&q) { return None; }
+        let target = ((q * self.total as f64).ceil() as u64).max(1);
+
+        let mut prefix = 0u64;
+        let mut range = 0;
+        for level in (0..self.levels.len() - 1).rev() {
+            let left = range << 1;
+            let left_weight = self.levels[level].estimate(left);
+            if prefix.saturating_add(left_weight) >= target {
+                range = left;
+            } else {
+                prefix = prefix.saturating_add(left_weight);
+                range = left + 1;
+            }
+        }
+        Some(range.min(self.universe - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform_sketch() -> DyadicCountMinSketch {
+        let mut sketch = DyadicCountMinSketch::new(0.001, 0.01, 1000).unwrap();
+        for key in 0..1000 {
+            sketch.update(key, None);
+        }
+        sketch
+    }
+
+    #[test]
+    fn large_frequencies_saturate() {
+        let mut sketch = DyadicCountMinSketch::new(0.01, 0.01, 16).unwrap();
+        sketch.update(3, Some(u64::MAX));
+        sketch.update(9, Some(1));
+        assert_eq!(u64::MAX, sketch.range_estimate(0, 15));
+        assert_eq!(Some(3), sketch.quantile(0.5));
+        // the total is saturated as well, key 3 alone already reaches it
+        assert_eq!(Some(3), sketch.quantile(1.0));
+    }
+
+    #[test]
+    fn range_estimate_sums_keys() {
+        let sketch = uniform_sketch();
+        assert_eq!(1, sketch.range_estimate(17, 17));
+        assert_eq!(100, sketch.range_estimate(100, 199));
+        assert_eq!(1000, sketch.range_estimate(0, 5000));
+        assert_eq!(0, sketch.range_estimate(10, 9));
+    }
+
+    #[test]
+    fn quantiles_of_uniform_stream() {
+        let sketch = uniform_sketch();
+        assert_eq!(Some(0), sketch.quantile(0.0));
+        assert_eq!(Some(499), sketch.quantile(0.5));
+        assert_eq!(Some(899), sketch.quantile(0.9));
+        assert_eq!(Some(999), sketch.quantile(1.0));
+        assert_eq!(None, sketch.quantile(1.5));
+    }
+
+    #[test]
+    fn out_of_universe_keys_are_ignored() {
+        let mut sketch = DyadicCountMinSketch::new(0.1, 0.1, 8).unwrap();
+        sketch.update(8, None);
+        sketch.update(3, Some(2));
+        assert_eq!(2, sketch.range_estimate(0, 7));
+        assert_eq!(0, sketch.estimate(8));
+        assert!(matches!(DyadicCountMinSketch::new(0.1, 0.1, 0), Err(CountMinError::WrongUniverse)));
+    }
+}
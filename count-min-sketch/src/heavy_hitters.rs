@@ -0,0 +1,113 @@
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+
+use crate::{CountMinError, CountMinSketch};
+
+/// Keeps track of the k most frequent values of a stream
+/// The counts come from a count-min-sketch, only the k candidate values are stored
+/// Candidates are kept ordered by their estimate, so the weakest one can be replaced in logarithmic time
+#[derive(Clone, Debug)]
+pub struct HeavyHitters<T> where T : Hash + Eq + Ord + Clone {
+    sketch: CountMinSketch,
+    k: usize,
+    candidates: HashMap<T, u64>,
+    ordered: BTreeSet<(u64, T)>,
+}
+
+impl<T> HeavyHitters<T> where T : Hash + Eq + Ord + Clone {
+    /// Creates a new heavy hitters tracker for k values
+    /// epsilon and delta are passed to the underlying count-min-sketch
+    pub fn new(k: usize, epsilon: f64, delta: f64) -> Result<Self, CountMinError> {
+        Ok(Self::with_sketch(k, CountMinSketch::new(epsilon, delta)?))
+    }
+
+    /// Creates a new heavy hitters tracker on top of an existing sketch
+    pub fn with_sketch(k: usize, sketch: CountMinSketch) -> Self {
+        Self {
+            sketch,
+            k,
+            candidates: HashMap::with_capacity(k),
+            ordered: BTreeSet::new(),
+        }
+    }
+
+    /// Updates the sketch, then the value becomes a candidate if its estimate beats the weakest one
+    pub fn update(&mut self, value: T, frequency: Option<u64>) {
+        self.sketch.update(&value, frequency);
+        if self.k == 0 { return; }
+
+        let estimate = self.sketch.estimate(&value);
+        if let Some(old_estimate) = self.candidates.get_mut(&value) {
+            self.ordered.remove(&(*old_estimate, value.clone()));
+            *old_estimate = estimate;
+            self.ordered.insert((estimate, value));
+            return;
+        }
+
+        if self.candidates.len() == self.k {
+            match self.ordered.first() {
+                Some((smallest, _)) if *smallest < estimate => {
+                    if let Some((_, evicted)) = self.ordered.pop_first() {
+                        self.candidates.remove(&evicted);
+                    }
+                },
+                _ => return,
+            }
+        }
+        self.candidates.insert(value.clone(), estimate);
+        self.ordered.insert((estimate, value));
+    }
+
+    /// Estimated frequency of any value, not only the candidates
+    pub fn estimate(&self, value: &T) -> u64 {
+        self.sketch.estimate(value)
+    }
+
+    /// Returns the current top-k values with their estimated counts, the most frequent first
+    pub fn top_k(&self) -> Vec<(T, u64)> {
+        self.ordered.iter().rev().map(|(count, value)| (value.clone(), *count)).collect()
+    }
+
+    /// The underlying sketch
+    pub fn sketch(&self) -> &CountMinSketch {
+        &self.sketch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_most_frequent_values() {
+        let mut heavy_hitters = HeavyHitters::new(3, 0.01, 0.01).unwrap();
+        for i in 0..100u64 {
+            heavy_hitters.update(i, None);
+        }
+        heavy_hitters.update(7, Some(50));
+        heavy_hitters.update(42, Some(40));
+        heavy_hitters.update(99, Some(30));
+
+        let top: Vec<u64> = heavy_hitters.top_k().into_iter().map(|(value, _)| value).collect();
+        assert_eq!(vec![7, 42, 99], top);
+    }
+
+    #[test]
+    fn top_k_is_bounded_and_ordered() {
+        let mut heavy_hitters = HeavyHitters::new(2, 0.1, 0.1).unwrap();
+        heavy_hitters.update("a", Some(1));
+        heavy_hitters.update("b", Some(2));
+        heavy_hitters.update("c", Some(3));
+        heavy_hitters.update("a", Some(5));
+
+        assert_eq!(vec![("a", 6), ("c", 3)], heavy_hitters.top_k());
+    }
+
+    #[test]
+    fn zero_k_tracks_nothing() {
+        let mut heavy_hitters = HeavyHitters::new(0, 0.1, 0.1).unwrap();
+        heavy_hitters.update(1, None);
+        assert!(heavy_hitters.top_k().is_empty());
+        assert_eq!(1, heavy_hitters.estimate(&1));
+    }
+}
@@ -0,0 +1,147 @@
+use std::hash::Hash;
+
+use crate::heavy_hitters::HeavyHitters;
+use crate::CountMinError;
+
+/// A heavy prefix found by HierarchicalHeavyHitters::query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeavyPrefix<T> {
+    pub prefix: Vec<T>,
+    /// Estimated traffic of the whole prefix
+    pub estimate: u64,
+    /// Traffic left after the heavy prefixes below it are taken out
+    pub discounted: u64,
+}
+
+/// Hierarchical heavy hitters for keys made of components, like IP addresses (octets) or paths (segments)
+/// Every level has its own count-min-sketch for the prefixes of that length, level 1 is the first component.
+/// Only a bounded number of candidate prefixes is kept per level, the counts come from the sketches
+#[derive(Clone, Debug)]
+pub struct HierarchicalHeavyHitters<T> where T : Hash + Eq + Ord + Clone {
+    levels: Vec<HeavyHitters<Vec<T>>>,
+    total: u64,
+}
+
+impl<T> HierarchicalHeavyHitters<T> where T : Hash + Eq + Ord + Clone {
+    /// Creates a new tracker for prefixes up to the given length
+    /// candidates is the number of prefixes kept per level, it should be at least 1 / phi of the queries
+    /// epsilon and delta are passed to every level's sketch
+    pub fn new(levels: usize, candidates: usize, epsilon: f64, delta: f64) -> Result<Self, CountMinError> {
+        if levels == 0 { return Err(CountMinError::EmptyHierarchy); }
+        let levels = (0..levels)
+            .map(|_| HeavyHitters::new(candidates, epsilon, delta))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { levels, total: 0 })
+    }
+
+    /// Updates every prefix of the key, components after the last level are ignored
+    pub fn update(&mut self, key: &[T], frequency: Option<u64>) {
+        let frequency = frequency.unwrap_or(1);
+        self.total = self.total.saturating_add(frequency);
+        for (level, heavy_hitters) in self.levels.iter_mut().enumerate().take(key.len()) {
+            heavy_hitters.update(key[..=level].to_vec(), Some(frequency));
+        }
+    }
+
+    /// Estimated traffic of the prefix
+    pub fn estimate(&self, prefix: &[T]) -> u64 {
+        match prefix.len() {
+            0 => self.total,
+            length if length <= self.levels.len() => self.levels[length - 1].estimate(&prefix.to_vec()),
+            _ => 0,
+        }
+    }
+
+    /// Sum of all frequencies
+    pub fn total_count(&self) -> u64 {
+        self.total
+    }
+
+    /// Prefixes carrying at least phi of the traffic, after the traffic of their heavy descendants is discounted
+    /// So a /16 is only reported if it's heavy without its heavy /24s. The longest prefixes come first
+    pub fn query(&self, phi: f64) -> Vec<HeavyPrefix<T>> {
+        let threshold = ((phi * self.total as f64).ceil() as u64).max(1);
+        let mut found: Vec<HeavyPrefix<T>> = Vec::new();
+        // heavy prefixes which aren't under another heavy prefix yet
+        let mut uncovered: Vec<usize> = Vec::new();
+
+        for heavy_hitters in self.levels.iter().rev() {
+            let mut covered_now = Vec::new();
+            let mut found_now = Vec::new();
+            for (prefix, estimate) in heavy_hitters.top_k() {
+                let below: Vec<usize> = uncovered.iter().copied()
+                    .filter(|&i| found[i].prefix.starts_with(&prefix))
+                    .collect();
+                let discount = below.iter().map(|&i| found[i].estimate).fold(0, u64::saturating_add);
+                let discounted = estimate.saturating_sub(discount);
+                if discounted >= threshold {
+                    covered_now.extend(below);
+                    found_now.push(HeavyPrefix { prefix, estimate, discounted });
+                }
+            }
+
+            uncovered.retain(|i| !covered_now.contains(i));
+            for heavy_prefix in found_now {
+                uncovered.push(found.len());
+                found.push(heavy_prefix);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_heavy_subnets() {
+        let mut hhh = HierarchicalHeavyHitters::new(4, 20, 0.001, 0.01).unwrap();
+        // a single heavy host
+        hhh.update(&[10, 0, 0, 1], Some(300));
+        // a heavy /24 made of many light hosts
+        for host in 0..=255u8 { hhh.update(&[192, 168, 1, host], Some(1)); }
+        // background traffic spread over many /8s
+        for net in 20..=219u8 { hhh.update(&[net, 1, 2, 3], Some(2)); }
+
+        let heavy = hhh.query(0.2);
+        let prefixes: Vec<Vec<u8>> = heavy.iter().map(|heavy_prefix| heavy_prefix.prefix.clone()).collect();
+        assert_eq!(vec![vec![10, 0, 0, 1], vec![192, 168, 1]], prefixes);
+        assert_eq!(256, heavy[1].discounted);
+    }
+
+    #[test]
+    fn large_frequencies_saturate() {
+        let mut hhh = HierarchicalHeavyHitters::new(2, 10, 0.01, 0.01).unwrap();
+        hhh.update(&[1, 1], Some(u64::MAX));
+        hhh.update(&[1, 2], Some(u64::MAX));
+        hhh.update(&[1, 3], Some(1));
+        assert_eq!(u64::MAX, hhh.total_count());
+
+        let heavy = hhh.query(0.5);
+        assert_eq!(2, heavy.len());
+        assert!(heavy.iter().all(|heavy_prefix| heavy_prefix.prefix.len() == 2));
+    }
+
+    #[test]
+    fn heavy_descendants_are_discounted() {
+        let mut hhh = HierarchicalHeavyHitters::new(2, 10, 0.01, 0.01).unwrap();
+        hhh.update(&["api", "users"], Some(60));
+        hhh.update(&["api", "orders"], Some(5));
+        hhh.update(&["api", "items"], Some(5));
+        hhh.update(&["static", "logo"], Some(30));
+
+        let heavy = hhh.query(0.25);
+        assert_eq!(HeavyPrefix { prefix: vec!["api", "users"], estimate: 60, discounted: 60 }, heavy[0]);
+        assert_eq!(HeavyPrefix { prefix: vec!["static", "logo"], estimate: 30, discounted: 30 }, heavy[1]);
+        // api is 70, but only 10 of it is left without api/users, static is left with nothing
+        assert_eq!(2, heavy.len());
+        assert_eq!(70, hhh.estimate(&["api"]));
+        assert_eq!(100, hhh.estimate(&[]));
+    }
+
+    #[test]
+    fn empty_hierarchy_is_rejected() {
+        assert!(matches!(HierarchicalHeavyHitters::<u8>::new(0, 10, 0.1, 0.1), Err(CountMinError::EmptyHierarchy)));
+    }
+}
@@ -1,21 +1,230 @@
-#![allow(dead_code)]
-
 use std::{hash::{Hash, Hasher}, collections::hash_map::DefaultHasher};
+use std::ops::{Add, AddAssign};
 use hashers::fnv::FNV1aHasher64;
 use thiserror::Error;
 use std::f64::consts::E;
 
+pub mod heavy_hitters;
+pub mod count_sketch;
+pub mod window;
+pub mod dyadic;
+pub mod concurrent;
+pub mod builder;
+pub mod counter;
+pub mod difference;
+mod snapshot;
+pub mod hierarchical;
+pub mod reversible;
+
+use builder::CountMinSketchBuilder;
+use counter::Counter;
+use difference::SketchDifference;
+
 #[derive(Error, Debug)]
 pub enum CountMinError {
-    #[error("epsilon and delta must be between 0 an 1")]
+    #[error("epsilon must be in (0, 1] and delta must be in (0, 1)")]
     WrongInput,
+    #[error("width and depth must be at least 1")]
+    WrongDimensions,
+    #[error("a {0}x{1} sketch has more counters than the memory can address")]
+    TooManyCounters(usize, usize),
+    #[error("memory budget of {0} bytes is not enough for a single column on every row")]
+    InsufficientMemory(usize),
+    #[error("sketch size must be given as error rate, dimensions or memory budget")]
+    MissingSize,
+    #[error("aging factor must be between 0 and 1")]
+    WrongAgingFactor,
+    #[error("sketches must use the same hash seed, found {0} and {1}")]
+    SeedMismatch(u64, u64),
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+    #[error("snapshot has {found} byte counters, expected {expected} byte counters")]
+    CounterMismatch { expected: usize, found: usize },
+    #[error("snapshots can't use HashStrategy::Default, its algorithm may change between Rust releases")]
+    NonPortableHashStrategy,
+    #[error("corrupted snapshot: {0}")]
+    CorruptedSnapshot(&'static str),
+    #[error("snapshot could not be read or written: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("sketches must have the same width and depth, found {0}x{1} and {2}x{3}")]
+    DimensionMismatch(usize, usize, usize, usize),
+    #[error("sketches must use the same hash strategy, found {0:?} and {1:?}")]
+    HashStrategyMismatch(HashStrategy, HashStrategy),
+    #[error("{0} needs UpdateMode::Standard")]
+    StandardModeRequired(&'static str),
+    #[error("window must be at least one tick long")]
+    EmptyWindow,
+    #[error("universe size must be between 1 and 2^63")]
+    WrongUniverse,
+    #[error("hierarchy must have at least one level")]
+    EmptyHierarchy,
+    #[error("key bits must be between 1 and 64")]
+    WrongKeyBits,
+}
+
+/// Hash function used for selecting the columns of every row
+/// The value is hashed only once, every row's column is derived from that hash
+/// Sketches can only be merged if they are using the same strategy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HashStrategy {
+    #[default]
+    XxHash,
+    Fnv,
+    /// Standard library's DefaultHasher, its algorithm may change between Rust releases
+    Default,
+}
+
+impl HashStrategy {
+    /// Full 64 bit hash of the value, the sketches derive their row positions from it
+    pub fn hash_value<T>(&self, value: &T) -> u64 where T : Hash {
+        self.hash_with_seed(value, 0)
+    }
+
+    /// Full 64 bit hash of the value with the seed
+    pub fn hash_with_seed<T>(&self, value: &T, seed: u64) -> u64 where T : Hash {
+        match self {
+            HashStrategy::XxHash => Self::xxhash(value, seed),
+            HashStrategy::Fnv => Self::fnv_hash(value, seed),
+            HashStrategy::Default => Self::default_hash(value, seed),
+        }
+    }
+
+    fn default_hash<T>(value: &T, seed: u64) -> u64
+    where T : Hash {
+        let mut default_hasher = DefaultHasher::new();
+        default_hasher.write_u64(seed);
+        value.hash(&mut default_hasher);
+        default_hasher.finish()
+    }
+
+    fn xxhash<T>(value: &T, seed: u64) -> u64
+    where T : Hash {
+        let mut xxhasher = xxhash_rust::xxh3::Xxh3::default();
+        xxhasher.write_u64(seed);
+        value.hash(&mut xxhasher);
+        xxhasher.finish()
+    }
+
+    fn fnv_hash<T>(value: &T, seed: u64) -> u64
+    where T : Hash {
+        let mut fnvhasher = FNV1aHasher64::default();
+        fnvhasher.write_u64(seed);
+        value.hash(&mut fnvhasher);
+        fnvhasher.finish()
+    }
+}
+
+/// Positions of a hash on every row of a width x depth table, as indexes of the flat vector
+/// Double hashing: row i uses (h1 + i * step) % width, where h1 and h2 are the halves of the hash.
+/// step is h2 reduced into 1..width, so consecutive rows never land on the same column
+pub(crate) fn row_indexes(hash: u64, width: usize, depth: usize) -> impl Iterator<Item = usize> {
+    let width = width as u64;
+    let h1 = (hash & 0xFFFF_FFFF) % width;
+    let step = if width > 1 { (hash >> 32) % (width - 1) + 1 } else { 0 };
+    (0..depth as u64).map(move |row| {
+        let column = (h1 + row * step % width) % width;
+        (column + row * width) as usize
+    })
+}
+
+/// Width and depth of a sketch for the given error rate and confidence
+pub(crate) fn dimensions(epsilon: f64, delta: f64) -> Result<(usize, usize), CountMinError> {
+    // written as negations, so NaN is rejected too
+    if !(epsilon > 0.0 && epsilon <= 1.0) {
+        return Err(CountMinError::WrongInput);
+    }
+
+    // a tiny epsilon saturates the width at usize::MAX
+    let width = (E / epsilon).ceil() as usize;
+    let depth = depth_for(delta)?;
+    cell_count(width, depth, std::mem::size_of::<u64>())?;
+    Ok((width, depth))
+}
+
+/// Number of counters of a width x depth table, if their bytes can be allocated
+pub(crate) fn cell_count(width: usize, depth: usize, cell_bytes: usize) -> Result<usize, CountMinError> {
+    width.checked_mul(depth)
+        .filter(|cells| cells.checked_mul(cell_bytes).is_some_and(|bytes| bytes <= isize::MAX as usize))
+        .ok_or(CountMinError::TooManyCounters(width, depth))
+}
+
+/// Depth of a sketch for the given confidence
+pub(crate) fn depth_for(delta: f64) -> Result<usize, CountMinError> {
+    if !(delta > 0.0 && delta < 1.0) {
+        return Err(CountMinError::WrongInput);
+    }
+    Ok((1. / delta).ln().ceil() as usize)
+}
+
+/// Median of the signed row estimates, the middle two are averaged for an even count
+pub(crate) fn median(values: &mut [i64]) -> i64 {
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        // widened, the sum of two large counts overflows i64
+        ((values[middle - 1] as i128 + values[middle] as i128) / 2) as i64
+    } else {
+        values[middle]
+    }
+}
+
+/// Error rate and confidence of a sketch with the given width and depth
+pub(crate) fn guarantees(width: usize, depth: usize) -> (f64, f64) {
+    (E / width as f64, (-(depth as f64)).exp())
+}
+
+/// Estimated inner product of two frequency vectors
+/// estimate is never below the real value, and with the sketch's confidence
+/// it's at most error_bound above it. u128, because products of large streams overflow u64
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InnerProductEstimate {
+    pub estimate: u128,
+    pub error_bound: u128,
+}
+
+/// Point estimate with the range the real frequency is in
+/// The real frequency is never above upper, and with the given confidence it's not below lower.
+/// upper is u64::MAX when one of the value's counters is saturated, the real frequency is unknown then
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub estimate: u64,
+    pub lower: u64,
+    pub upper: u64,
+    pub confidence: f64,
+}
+
+/// How the counters are increased on update
+/// Standard increases every selected counter by the frequency
+/// Conservative only raises the selected counters which are below the new minimum estimate,
+/// it never underestimates but overestimates far less for the infrequent values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UpdateMode {
+    #[default]
+    Standard,
+    Conservative,
 }
 
 /// Base data type for count-min-sketch
-pub struct CountMinSketch {
-    data: Vec<u64>,
+/// Counters are u64 by default, smaller counters (u8, u16, u32) can be chosen through the builder.
+/// Small counters saturate at their max value, estimates are always returned as u64
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "snapshot::RawCountMinSketch<C>"))]
+pub struct CountMinSketch<C: Counter = u64> {
+    data: Vec<C>,
     depth: usize,
     width: usize,
+    epsilon: f64,
+    delta: f64,
+    mode: UpdateMode,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "snapshot::serialize_hash_strategy"))]
+    hash_strategy: HashStrategy,
+    seed: u64,
+    total: u64,
+    reset_interval: Option<u64>,
+    updates_since_reset: u64,
 }
 
 impl CountMinSketch {
@@ -24,59 +233,320 @@ impl CountMinSketch {
     /// delta is the confidence to correctness of estimate, the smaller the higher we're more confident 
     /// both epsilon and delta must be between 0 and 1, for instance 0,5
     pub fn new(epsilon: f64, delta: f64) -> Result<Self, CountMinError> {
-        if epsilon < 0.0 || delta < 0.0 || epsilon > 1.0 || delta > 1.0 {
-            return Err(CountMinError::WrongInput);
-        }
+        Self::with_mode(epsilon, delta, UpdateMode::Standard)
+    }
+
+    /// Creates a new count-min-sketch with the given update mode
+    pub fn with_mode(epsilon: f64, delta: f64, mode: UpdateMode) -> Result<Self, CountMinError> {
+        Self::with_options(epsilon, delta, mode, HashStrategy::default())
+    }
+
+    /// Creates a new count-min-sketch with the given update mode and hash strategy
+    pub fn with_options(epsilon: f64, delta: f64, mode: UpdateMode, hash_strategy: HashStrategy) -> Result<Self, CountMinError> {
+        let (width, depth) = dimensions(epsilon, delta)?;
+        Ok(Self::from_parts(width, depth, epsilon, delta, mode, hash_strategy))
+    }
+
+    /// Creates a new count-min-sketch with the exact number of columns and rows
+    /// epsilon and delta are derived from them, e / width and e^-depth
+    pub fn with_dimensions(width: usize, depth: usize) -> Result<Self, CountMinError> {
+        Self::builder().dimensions(width, depth).build()
+    }
 
-        let width = (E / epsilon).ceil() as usize;
-        let depth = (1. / delta).ln().ceil() as usize;
-        Ok(Self {
-            data: vec![0; width * depth],
+    /// Creates the most accurate count-min-sketch whose counters fit in the given bytes
+    /// delta decides the depth, the rest of the budget goes to the width
+    pub fn with_memory_budget(bytes: usize, delta: f64) -> Result<Self, CountMinError> {
+        Self::builder().memory_budget(bytes, delta).build()
+    }
+
+    /// Builder for choosing the size, update mode, hash strategy and counter type together
+    pub fn builder() -> CountMinSketchBuilder {
+        CountMinSketchBuilder::default()
+    }
+}
+
+impl<C: Counter> CountMinSketch<C> {
+    pub(crate) fn from_parts(width: usize, depth: usize, epsilon: f64, delta: f64, mode: UpdateMode, hash_strategy: HashStrategy) -> Self {
+        Self {
+            data: vec![C::default(); width * depth],
             width,
-            depth
-        })
+            depth,
+            epsilon,
+            delta,
+            mode,
+            hash_strategy,
+            seed: 0,
+            total: 0,
+            reset_interval: None,
+            updates_since_reset: 0,
+        }
+    }
+
+    /// Bytes used by the counters
+    pub fn memory_usage(&self) -> usize {
+        self.data.len() * std::mem::size_of::<C>()
+    }
+
+    /// Returns true if any counter has reached its max value
+    /// Estimates of the values on those counters may be lower than the real frequency
+    pub fn is_saturated(&self) -> bool {
+        self.data.contains(&C::MAX)
+    }
+
+    /// Number of counters on every row
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of rows
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Error rate, given while creating the sketch or derived from the width
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Probability of an estimate to exceed the error rate, given while creating the sketch or derived from the depth
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    /// Sum of all frequencies the sketch has seen
+    pub fn total_count(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of updates after which the counters are halved automatically, if any
+    pub fn reset_interval(&self) -> Option<u64> {
+        self.reset_interval
+    }
+
+    /// Halves every counter automatically after the given number of updates, None disables it
+    /// This is the reset operation of TinyLFU, it keeps the counters fresh for cache admission
+    pub fn set_reset_interval(&mut self, updates: Option<u64>) {
+        self.reset_interval = updates.filter(|updates| *updates > 0);
+        self.updates_since_reset = 0;
+    }
+
+    /// Returns the update mode which is selected while creating the sketch
+    pub fn mode(&self) -> UpdateMode {
+        self.mode
+    }
+
+    /// Returns the hash strategy which is selected while creating the sketch
+    pub fn hash_strategy(&self) -> HashStrategy {
+        self.hash_strategy
+    }
+
+    /// Seed of the hash function, sketches with different seeds put the same value on different columns
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 
     pub fn update<T>(&mut self, value: T, frequency: Option<u64>) where T : Hash {
-        for i in 0..self.depth {
-            self.data[Self::xxhash(self.width  as u64, &value, i as u64) + (i * self.width)] += frequency.unwrap_or(1)
+        let frequency = frequency.unwrap_or(1);
+        self.total = self.total.saturating_add(frequency);
+        match self.mode {
+            UpdateMode::Standard => {
+                let frequency = C::from_u64(frequency);
+                for index in self.indexes(&value) {
+                    self.data[index] = self.data[index].saturating_add(frequency);
+                }
+            },
+            UpdateMode::Conservative => {
+                let indexes: Vec<usize> = self.indexes(&value).collect();
+                let smallest = indexes.iter().map(|&index| self.data[index]).min().unwrap_or_default();
+                let new_estimate = C::from_u64(smallest.to_u64().saturating_add(frequency));
+                for index in indexes {
+                    if self.data[index] < new_estimate { self.data[index] = new_estimate; }
+                }
+            }
+        }
+
+        if let Some(interval) = self.reset_interval {
+            self.updates_since_reset += 1;
+            if self.updates_since_reset >= interval {
+                self.halve();
+            }
         }
     }
 
     pub fn estimate<T>(&self, value: T) -> u64 where T : Hash {
         let mut smallest = u64::MAX;
-        for i in 0..self.depth {
-            let count = self.data[Self::xxhash(self.width as u64, &value, i as u64) + (i * self.width)];
+        for index in self.indexes(&value) {
+            let count = self.data[index].to_u64();
             if count < smallest { smallest = count; }
         }
         smallest
     }
 
-    fn default_hash<T>(capacity: u64, value: &T, seed: u64) -> usize  
-    where T : Hash {
-        let mut default_hasher = DefaultHasher::new();
-        default_hasher.write_u64(seed);
-        value.hash(&mut default_hasher);
-        let result = default_hasher.finish() % capacity;
-        result.try_into().unwrap()
+    /// Updates every value in the slice once
+    pub fn update_batch<T>(&mut self, values: &[T]) where T : Hash {
+        for value in values {
+            self.update(value, None);
+        }
     }
 
-    fn xxhash<T>(capacity: u64, value: &T, seed: u64) -> usize  
-    where T : Hash {
-        let mut xxhasher = xxhash_rust::xxh3::Xxh3::default();
-        xxhasher.write_u64(seed);
-        value.hash(&mut xxhasher);
-        let result = xxhasher.finish() % capacity;
-        result.try_into().unwrap()
+    /// Estimates every value in the slice, in the same order
+    pub fn estimate_batch<T>(&self, values: &[T]) -> Vec<u64> where T : Hash {
+        values.iter().map(|value| self.estimate(value)).collect()
     }
 
-    fn fnv_hash<T>(capacity: u64, value: &T, seed: u64) -> usize  
-    where T : Hash {
-        let mut fnvhasher = FNV1aHasher64::default();
-        fnvhasher.write_u64(seed);
-        value.hash(&mut fnvhasher);
-        let result = fnvhasher.finish() % capacity;
-        result.try_into().unwrap()
+    /// Estimate with its error bounds
+    /// The estimate exceeds the real frequency by at most epsilon times the total count, with 1 - delta confidence.
+    /// The confidence assumes independent rows, the rows are derived from a single hash by double hashing
+    /// which keeps the same bound asymptotically (Kirsch and Mitzenmacher), not exactly
+    pub fn estimate_with_bounds<T>(&self, value: T) -> Estimate where T : Hash {
+        let saturated = self.indexes(&value).any(|index| self.data[index] == C::MAX);
+        let estimate = self.estimate(value);
+        let error = (self.epsilon * self.total as f64).ceil() as u64;
+        Estimate {
+            estimate,
+            lower: estimate.saturating_sub(error),
+            upper: if saturated { u64::MAX } else { estimate },
+            confidence: 1.0 - self.delta,
+        }
+    }
+
+    /// Count-Mean-Min estimate, less biased than estimate when the stream is large compared to the width
+    /// Every row's counter is reduced by the expected noise from the other values hashed into it,
+    /// then the median of the rows is taken. It never returns more than estimate.
+    /// The noise is derived from the total stream count, so it assumes standard update mode
+    pub fn estimate_count_mean_min<T>(&self, value: T) -> u64 where T : Hash {
+        let counts: Vec<u64> = self.indexes(&value).map(|index| self.data[index].to_u64()).collect();
+        let estimate = counts.iter().copied().min().unwrap_or(u64::MAX);
+        if self.width < 2 { return estimate; }
+
+        let mut residues: Vec<f64> = counts.into_iter().map(|count| {
+            let count = count as f64;
+            let noise = (self.total as f64 - count) / (self.width - 1) as f64;
+            count - noise
+        }).collect();
+        residues.sort_by(|a, b| a.total_cmp(b));
+
+        let middle = residues.len() / 2;
+        let median = if residues.len().is_multiple_of(2) {
+            (residues[middle - 1] + residues[middle]) / 2.0
+        } else {
+            residues[middle]
+        };
+        (median.max(0.0).round() as u64).min(estimate)
+    }
+
+    /// Multiplies every counter by the factor, rounding down, so the old popularity fades
+    /// factor must be between 0 and 1
+    pub fn age(&mut self, factor: f64) -> Result<(), CountMinError> {
+        if !(0.0..=1.0).contains(&factor) { return Err(CountMinError::WrongAgingFactor); }
+        self.data.iter_mut().for_each(|cell| *cell = C::from_u64((cell.to_u64() as f64 * factor) as u64));
+        self.total = (self.total as f64 * factor) as u64;
+        self.updates_since_reset = 0;
+        Ok(())
+    }
+
+    /// Halves every counter, rounding down
+    pub fn halve(&mut self) {
+        self.data.iter_mut().for_each(|cell| *cell = C::from_u64(cell.to_u64() / 2));
+        self.total /= 2;
+        self.updates_since_reset = 0;
+    }
+
+    /// Resets every counter to zero, keeps the configuration
+    pub fn clear(&mut self) {
+        self.data.iter_mut().for_each(|cell| *cell = C::default());
+        self.total = 0;
+        self.updates_since_reset = 0;
+    }
+
+    /// Adds the counters of the other sketch to this one, cell by cell
+    /// Both sketches must have the same width and depth, so that a value lands on the same cells in both
+    pub fn merge(&mut self, other: &CountMinSketch<C>) -> Result<(), CountMinError> {
+        self.check_compatible(other)?;
+        for (cell, other_cell) in self.data.iter_mut().zip(other.data.iter()) {
+            *cell = cell.saturating_add(*other_cell);
+        }
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+
+    /// Returns a new sketch which is the sum of the both sketches
+    pub fn union(&self, other: &CountMinSketch<C>) -> Result<Self, CountMinError> {
+        let mut result = self.clone();
+        result.merge(other)?;
+        Ok(result)
+    }
+
+    /// Subtracts the other sketch's counters from this one's, cell by cell, into signed counters
+    /// Useful for comparing two time windows, see SketchDifference::change_estimate
+    pub fn subtract(&self, other: &CountMinSketch<C>) -> Result<SketchDifference, CountMinError> {
+        self.check_compatible(other)?;
+        let data = self.data.iter().zip(other.data.iter())
+            .map(|(a, b)| {
+                let difference = a.to_u64() as i128 - b.to_u64() as i128;
+                difference.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+            })
+            .collect();
+        Ok(SketchDifference::new(data, self.width, self.depth, self.hash_strategy, self.seed))
+    }
+
+    /// Estimates the dot product of the frequency vectors of the both streams, for instance a join size
+    /// Every row gives an overestimate, so the smallest row product is taken
+    /// The error bound is epsilon times the total counts of the both streams.
+    /// Both sketches must be in standard update mode, a conservative cell isn't the sum of its values
+    /// so the product could be below the real one
+    pub fn inner_product(&self, other: &CountMinSketch<C>) -> Result<InnerProductEstimate, CountMinError> {
+        if self.mode != UpdateMode::Standard || other.mode != UpdateMode::Standard {
+            return Err(CountMinError::StandardModeRequired("inner product"));
+        }
+        self.check_compatible(other)?;
+        let mut smallest = u128::MAX;
+        for row in 0..self.depth {
+            let cells = (row * self.width)..((row + 1) * self.width);
+            let product: u128 = self.data[cells.clone()].iter().zip(other.data[cells].iter())
+                .map(|(a, b)| a.to_u64() as u128 * b.to_u64() as u128)
+                .sum();
+            if product < smallest { smallest = product; }
+        }
+
+        let error_bound = (self.epsilon * self.total as f64 * other.total as f64).ceil() as u128;
+        Ok(InnerProductEstimate { estimate: smallest, error_bound })
+    }
+
+    fn check_compatible(&self, other: &CountMinSketch<C>) -> Result<(), CountMinError> {
+        if self.width != other.width || self.depth != other.depth {
+            return Err(CountMinError::DimensionMismatch(self.width, self.depth, other.width, other.depth));
+        }
+        if self.hash_strategy != other.hash_strategy {
+            return Err(CountMinError::HashStrategyMismatch(self.hash_strategy, other.hash_strategy));
+        }
+        if self.seed != other.seed {
+            return Err(CountMinError::SeedMismatch(self.seed, other.seed));
+        }
+        Ok(())
+    }
+
+    // positions of the value's counters, one per row
+    fn indexes<T>(&self, value: &T) -> impl Iterator<Item = usize> where T : Hash {
+        row_indexes(self.hash_strategy.hash_with_seed(value, self.seed), self.width, self.depth)
+    }
+}
+
+impl<C: Counter> Add for CountMinSketch<C> {
+    type Output = Result<CountMinSketch<C>, CountMinError>;
+
+    fn add(mut self, other: CountMinSketch<C>) -> Self::Output {
+        self.merge(&other)?;
+        Ok(self)
+    }
+}
+
+/// Panics if the sketches are not compatible, use merge to handle the error
+impl<C: Counter> AddAssign for CountMinSketch<C> {
+    fn add_assign(&mut self, other: CountMinSketch<C>) {
+        if let Err(e) = self.merge(&other) {
+            panic!("{}", e);
+        }
     }
 }
 
@@ -92,6 +562,20 @@ mod tests {
         assert_eq!(1, result);
     }
 
+    #[test]
+    fn hash_strategy_is_hashable() {
+        let mut hasher = DefaultHasher::new();
+        HashStrategy::Fnv.hash(&mut hasher);
+        assert_ne!(0, hasher.finish());
+    }
+
+    #[test]
+    fn median_of_large_counts() {
+        assert_eq!(i64::MAX, median(&mut [i64::MAX, i64::MAX]));
+        assert_eq!(i64::MIN, median(&mut [i64::MIN, i64::MIN, 0, i64::MIN]));
+        assert_eq!(-3, median(&mut [-3, -4]));
+    }
+
     #[test]
     fn same_element_multiple_times_updated() {
         let mut sketch = CountMinSketch::new(0.1, 0.1).unwrap();
@@ -130,4 +614,284 @@ mod tests {
             let result3 = sketch.estimate(5);
             assert_eq!(1, result3);
         }
-}
\ No newline at end of file
+
+    #[test]
+    fn merged_sketch_sums_estimates() {
+        let mut first = CountMinSketch::new(0.1, 0.1).unwrap();
+        let mut second = CountMinSketch::new(0.1, 0.1).unwrap();
+        first.update(5, Some(2));
+        second.update(5, Some(3));
+        second.update(7, Some(1));
+        first.merge(&second).unwrap();
+        assert_eq!(5, first.estimate(5));
+        assert_eq!(1, first.estimate(7));
+    }
+
+    #[test]
+    fn merge_rejects_different_dimensions() {
+        let mut first = CountMinSketch::new(0.1, 0.1).unwrap();
+        let second = CountMinSketch::new(0.01, 0.1).unwrap();
+        assert!(matches!(first.merge(&second), Err(CountMinError::DimensionMismatch(..))));
+        assert!((first + second).is_err());
+    }
+
+    #[test]
+    fn merge_rejects_different_hash_strategies() {
+        let mut first = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::XxHash).unwrap();
+        let second = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::Fnv).unwrap();
+        assert!(matches!(first.merge(&second), Err(CountMinError::HashStrategyMismatch(..))));
+    }
+
+    #[test]
+    fn every_hash_strategy_estimates() {
+        for strategy in [HashStrategy::XxHash, HashStrategy::Fnv, HashStrategy::Default] {
+            let mut sketch = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, strategy).unwrap();
+            sketch.update("a", Some(3));
+            sketch.update("b", None);
+            assert_eq!(strategy, sketch.hash_strategy());
+            assert_eq!(3, sketch.estimate("a"));
+        }
+    }
+
+    #[test]
+    fn inner_product_of_disjoint_and_same_streams() {
+        let mut first = CountMinSketch::new(0.01, 0.01).unwrap();
+        let mut second = CountMinSketch::new(0.01, 0.01).unwrap();
+        first.update("a", Some(3));
+        first.update("b", Some(2));
+        second.update("a", Some(4));
+        second.update("c", Some(5));
+
+        let result = first.inner_product(&second).unwrap();
+        assert_eq!(12, result.estimate);
+        assert_eq!((0.01 * 5.0 * 9.0_f64).ceil() as u128, result.error_bound);
+        assert_eq!(13, first.inner_product(&first).unwrap().estimate);
+    }
+
+    #[test]
+    fn inner_product_rejects_incompatible_sketches() {
+        let first = CountMinSketch::new(0.1, 0.1).unwrap();
+        let second = CountMinSketch::new(0.1, 0.01).unwrap();
+        assert!(matches!(first.inner_product(&second), Err(CountMinError::DimensionMismatch(..))));
+
+        let conservative = CountMinSketch::with_mode(0.1, 0.1, UpdateMode::Conservative).unwrap();
+        assert!(matches!(first.inner_product(&conservative), Err(CountMinError::StandardModeRequired(_))));
+        assert!(matches!(conservative.inner_product(&first), Err(CountMinError::StandardModeRequired(_))));
+        assert!(matches!(conservative.inner_product(&conservative), Err(CountMinError::StandardModeRequired(_))));
+    }
+
+    #[test]
+    fn accessors_report_configuration() {
+        let mut sketch = CountMinSketch::new(0.1, 0.05).unwrap();
+        sketch.update(1, Some(3));
+        sketch.update(2, None);
+        assert_eq!(28, sketch.width());
+        assert_eq!(3, sketch.depth());
+        assert_eq!(0.1, sketch.epsilon());
+        assert_eq!(0.05, sketch.delta());
+        assert_eq!(4, sketch.total_count());
+    }
+
+    #[test]
+    fn estimate_with_bounds_uses_total_count() {
+        let mut sketch = CountMinSketch::new(0.1, 0.05).unwrap();
+        for i in 0..100u64 { sketch.update(i, None); }
+        sketch.update("a", Some(20));
+
+        let result = sketch.estimate_with_bounds("a");
+        assert_eq!(sketch.estimate("a"), result.estimate);
+        assert_eq!(result.estimate, result.upper);
+        // total count is 120, so the error is 12
+        assert_eq!(result.estimate - 12, result.lower);
+        assert!(result.lower <= 20 && 20 <= result.upper);
+        assert!((result.confidence - 0.95).abs() < 1e-9);
+    }
+
+    #[test]
+    fn saturated_counters_have_no_upper_bound() {
+        let mut sketch = CountMinSketch::builder().error_rate(0.1, 0.1).counter::<u8>().build().unwrap();
+        sketch.update(1, Some(1000));
+        sketch.update(2, Some(7));
+        let result = sketch.estimate_with_bounds(1);
+        assert_eq!(255, result.estimate);
+        assert_eq!(u64::MAX, result.upper);
+        assert!(result.lower <= 1000);
+        assert_eq!(7, sketch.estimate_with_bounds(2).upper);
+    }
+
+    #[test]
+    fn batch_update_and_estimate() {
+        let mut sketch = CountMinSketch::new(0.01, 0.01).unwrap();
+        sketch.update_batch(&["a", "b", "a", "c", "a"]);
+        assert_eq!(vec![3, 1, 1, 0], sketch.estimate_batch(&["a", "b", "c", "d"]));
+        assert_eq!(5, sketch.total_count());
+    }
+
+    #[test]
+    fn rows_use_different_columns() {
+        // step is 3 % 9 + 1
+        let indexes: Vec<usize> = row_indexes(0x0000_0003_0000_0005, 10, 4).collect();
+        assert_eq!(vec![5, 19, 23, 37], indexes);
+        // 2719 is the width for epsilon 0.001, hashes whose upper half is a multiple of it used to collapse
+        for width in [2, 3, 2719, 4096] {
+            for upper in [0, 2718, 2719, 2720, 5438, u32::MAX as u64] {
+                let hash = (upper << 32) | 12345;
+                let columns: Vec<usize> = row_indexes(hash, width, 2).enumerate()
+                    .map(|(row, index)| index - row * width)
+                    .collect();
+                assert_ne!(columns[0], columns[1]);
+                assert!(columns.iter().all(|&column| column < width));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_and_out_of_range_inputs_are_rejected() {
+        assert!(matches!(CountMinSketch::new(0.0, 0.1), Err(CountMinError::WrongInput)));
+        assert!(matches!(CountMinSketch::new(0.1, 0.0), Err(CountMinError::WrongInput)));
+        assert!(matches!(CountMinSketch::new(0.1, 1.0), Err(CountMinError::WrongInput)));
+        assert!(matches!(CountMinSketch::new(f64::NAN, 0.1), Err(CountMinError::WrongInput)));
+        assert!(matches!(CountMinSketch::with_dimensions(0, 3), Err(CountMinError::WrongDimensions)));
+        assert!(matches!(CountMinSketch::with_dimensions(usize::MAX, 2), Err(CountMinError::TooManyCounters(..))));
+        assert!(matches!(CountMinSketch::new(1e-300, 0.1), Err(CountMinError::TooManyCounters(..))));
+    }
+
+    #[test]
+    fn with_dimensions_reports_guarantees() {
+        let sketch = CountMinSketch::with_dimensions(100, 5).unwrap();
+        assert_eq!(100, sketch.width());
+        assert_eq!(5, sketch.depth());
+        assert!((sketch.epsilon() - E / 100.0).abs() < 1e-12);
+        assert!((sketch.delta() - (-5.0f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn with_memory_budget_fits_in_budget() {
+        let sketch = CountMinSketch::with_memory_budget(8 * 1024, 0.01).unwrap();
+        assert_eq!(5, sketch.depth());
+        assert_eq!(204, sketch.width());
+        assert!(sketch.memory_usage() <= 8 * 1024);
+        assert!(matches!(CountMinSketch::with_memory_budget(16, 0.01), Err(CountMinError::InsufficientMemory(16))));
+    }
+
+    #[test]
+    fn small_counters_saturate() {
+        let mut sketch: CountMinSketch<u8> = CountMinSketch::builder().error_rate(0.1, 0.1).counter::<u8>().build().unwrap();
+        sketch.update(1, Some(200));
+        assert!(!sketch.is_saturated());
+        sketch.update(1, Some(100));
+        assert!(sketch.is_saturated());
+        assert_eq!(255, sketch.estimate(1));
+        assert_eq!(300, sketch.total_count());
+
+        sketch.update(2, Some(1000));
+        assert_eq!(255, sketch.estimate(2));
+    }
+
+    #[test]
+    fn small_counters_save_memory() {
+        let wide = CountMinSketch::with_dimensions(100, 4).unwrap();
+        let narrow = CountMinSketch::builder().dimensions(100, 4).counter::<u16>().build().unwrap();
+        assert_eq!(4 * wide.memory_usage(), 16 * narrow.memory_usage());
+    }
+
+    #[test]
+    fn conservative_update_saturates() {
+        let mut sketch = CountMinSketch::builder()
+            .error_rate(0.1, 0.1)
+            .mode(UpdateMode::Conservative)
+            .counter::<u8>()
+            .build()
+            .unwrap();
+        sketch.update("a", Some(250));
+        sketch.update("a", Some(10));
+        assert_eq!(255, sketch.estimate("a"));
+    }
+
+    #[test]
+    fn halve_and_age_scale_counters() {
+        let mut sketch = CountMinSketch::new(0.1, 0.1).unwrap();
+        sketch.update("a", Some(9));
+        sketch.update("b", Some(100));
+        sketch.halve();
+        assert_eq!(4, sketch.estimate("a"));
+        assert_eq!(50, sketch.estimate("b"));
+
+        sketch.age(0.1).unwrap();
+        assert_eq!(0, sketch.estimate("a"));
+        assert_eq!(5, sketch.estimate("b"));
+        assert!(matches!(sketch.age(1.5), Err(CountMinError::WrongAgingFactor)));
+    }
+
+    #[test]
+    fn counters_are_halved_after_reset_interval() {
+        let mut sketch = CountMinSketch::builder().error_rate(0.1, 0.1).reset_after(4).build().unwrap();
+        assert_eq!(Some(4), sketch.reset_interval());
+        for _ in 0..3 { sketch.update("a", Some(2)); }
+        assert_eq!(6, sketch.estimate("a"));
+        sketch.update("a", Some(2));
+        assert_eq!(4, sketch.estimate("a"));
+
+        sketch.set_reset_interval(None);
+        for _ in 0..10 { sketch.update("a", None); }
+        assert_eq!(14, sketch.estimate("a"));
+    }
+
+    #[test]
+    fn add_assign_merges() {
+        let mut first = CountMinSketch::new(0.1, 0.1).unwrap();
+        let mut second = CountMinSketch::new(0.1, 0.1).unwrap();
+        first.update("a", None);
+        second.update("a", None);
+        first += second;
+        assert_eq!(2, first.estimate("a"));
+    }
+
+    fn skewed_stream_error(mode: UpdateMode) -> u64 {
+        let mut sketch = CountMinSketch::with_mode(0.1, 0.01, mode).unwrap();
+        // a few heavy values and a long tail of values seen only once
+        for i in 0..5u64 { sketch.update(i, Some(1000 / (i + 1))); }
+        for i in 5..500u64 { sketch.update(i, None); }
+
+        let mut error = 0;
+        for i in 0..5u64 { error += sketch.estimate(i) - 1000 / (i + 1); }
+        for i in 5..500u64 { error += sketch.estimate(i) - 1; }
+        error
+    }
+
+    #[test]
+    fn conservative_update_keeps_exact_counts() {
+        let mut sketch = CountMinSketch::with_mode(0.1, 0.1, UpdateMode::Conservative).unwrap();
+        sketch.update(5, Some(2));
+        sketch.update(5, None);
+        assert_eq!(UpdateMode::Conservative, sketch.mode());
+        assert_eq!(3, sketch.estimate(5));
+    }
+
+    #[test]
+    fn conservative_update_reduces_error_on_skewed_stream() {
+        let standard = skewed_stream_error(UpdateMode::Standard);
+        let conservative = skewed_stream_error(UpdateMode::Conservative);
+        assert!(conservative < standard, "conservative: {}, standard: {}", conservative, standard);
+    }
+
+    #[test]
+    fn count_mean_min_reduces_bias_on_large_stream() {
+        let mut sketch = CountMinSketch::new(0.5, 0.01).unwrap();
+        for i in 0..1000u64 { sketch.update(i, None); }
+        sketch.update("needle", Some(50));
+
+        let estimate = sketch.estimate("needle");
+        let count_mean_min = sketch.estimate_count_mean_min("needle");
+        assert!(count_mean_min <= estimate);
+        assert!(count_mean_min.abs_diff(50) < estimate - 50,
+            "count-mean-min: {}, estimate: {}", count_mean_min, estimate);
+    }
+
+    #[test]
+    fn count_mean_min_is_exact_for_single_value() {
+        let mut sketch = CountMinSketch::new(0.1, 0.1).unwrap();
+        sketch.update(5, Some(4));
+        assert_eq!(4, sketch.estimate_count_mean_min(5));
+    }
+}
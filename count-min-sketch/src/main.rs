@@ -0,0 +1,204 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use cm_sketch::heavy_hitters::HeavyHitters;
+use cm_sketch::CountMinSketch;
+
+const USAGE: &str = "\
+Usage: cm-sketch [OPTIONS] [FILE]
+
+Counts the newline-delimited items of FILE, or of stdin when FILE is missing or -
+
+Options:
+  --epsilon <E>       error rate of the sketch, default 0.001
+  --delta <D>         probability of exceeding the error rate, default 0.01
+  --estimate <KEY>    prints the estimated count of the key, can be repeated
+  --top <K>           prints the K most frequent items
+  --save <PATH>       writes the sketch to a snapshot file
+  --load <PATH>       queries a snapshot file instead of reading items
+  -h, --help          prints this message";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    epsilon: f64,
+    delta: f64,
+    // true if --epsilon or --delta is given, a loaded snapshot already has its size
+    size_given: bool,
+    estimates: Vec<String>,
+    top: Option<usize>,
+    save: Option<String>,
+    load: Option<String>,
+    input: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            epsilon: 0.001,
+            delta: 0.01,
+            size_given: false,
+            estimates: Vec::new(),
+            top: None,
+            save: None,
+            load: None,
+            input: None,
+        }
+    }
+}
+
+// None means help is asked
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--epsilon" => {
+                options.epsilon = parse_number(&value("--epsilon")?, "--epsilon")?;
+                options.size_given = true;
+            },
+            "--delta" => {
+                options.delta = parse_number(&value("--delta")?, "--delta")?;
+                options.size_given = true;
+            },
+            "--estimate" => options.estimates.push(value("--estimate")?),
+            "--top" => options.top = Some(parse_number(&value("--top")?, "--top")?),
+            "--save" => options.save = Some(value("--save")?),
+            "--load" => options.load = Some(value("--load")?),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            input => {
+                if options.input.is_some() { return Err("only one input file is allowed".to_string()); }
+                options.input = Some(input.to_string());
+            }
+        }
+    }
+
+    if options.load.is_some() && options.input.is_some() {
+        return Err("--load queries a snapshot, it can't be used with an input file".to_string());
+    }
+    if options.load.is_some() && options.size_given {
+        return Err("snapshots keep their size, --epsilon and --delta can't be used with --load".to_string());
+    }
+    if options.load.is_some() && options.top.is_some() {
+        return Err("snapshots don't keep the items, --top needs the input".to_string());
+    }
+    Ok(Some(options))
+}
+
+fn parse_number<N: std::str::FromStr>(value: &str, name: &str) -> Result<N, String> {
+    value.parse().map_err(|_| format!("{} has an invalid value: {}", name, value))
+}
+
+fn read_items<R: BufRead>(reader: R, heavy_hitters: &mut HeavyHitters<String>) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        let item = line.trim_end_matches('\r');
+        if !item.is_empty() {
+            heavy_hitters.update(item.to_string(), None);
+        }
+    }
+    Ok(())
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut output = BufWriter::new(io::stdout().lock());
+    let sketch = match &options.load {
+        Some(path) => {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+            CountMinSketch::read_from(BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))?
+        },
+        None => {
+            let sketch = CountMinSketch::new(options.epsilon, options.delta).map_err(|e| e.to_string())?;
+            let mut heavy_hitters = HeavyHitters::with_sketch(options.top.unwrap_or(0), sketch);
+            match options.input.as_deref() {
+                None | Some("-") => read_items(io::stdin().lock(), &mut heavy_hitters),
+                Some(path) => {
+                    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+                    read_items(BufReader::new(file), &mut heavy_hitters)
+                },
+            }.map_err(|e| format!("reading items: {}", e))?;
+
+            if options.top.is_some() {
+                for (item, count) in heavy_hitters.top_k() {
+                    writeln!(output, "{}\t{}", item, count).map_err(|e| e.to_string())?;
+                }
+            }
+            heavy_hitters.sketch().clone()
+        },
+    };
+
+    for key in &options.estimates {
+        writeln!(output, "{}\t{}", key, sketch.estimate(key)).map_err(|e| e.to_string())?;
+    }
+    output.flush().map_err(|e| e.to_string())?;
+
+    if let Some(path) = &options.save {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        sketch.write_to(BufWriter::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("cm-sketch: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        },
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("cm-sketch: {}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse(&["--epsilon", "0.01", "--estimate", "a", "--estimate", "b", "--top", "5", "log.txt"])
+            .unwrap().unwrap();
+        assert_eq!(0.01, options.epsilon);
+        assert_eq!(0.01, options.delta);
+        assert_eq!(vec!["a", "b"], options.estimates);
+        assert_eq!(Some(5), options.top);
+        assert_eq!(Some("log.txt".to_string()), options.input);
+    }
+
+    #[test]
+    fn rejects_wrong_options() {
+        assert!(parse(&["--epsilon"]).is_err());
+        assert!(parse(&["--top", "many"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["--load", "sketch.bin", "--top", "3"]).is_err());
+        assert!(parse(&["--load", "sketch.bin", "--epsilon", "0.01"]).is_err());
+        assert!(parse(&["--delta", "0.1", "--load", "sketch.bin"]).is_err());
+        assert!(parse(&["--load", "sketch.bin", "--estimate", "a"]).unwrap().is_some());
+        assert_eq!(None, parse(&["--help"]).unwrap());
+    }
+
+    #[test]
+    fn reads_items_by_line() {
+        let mut heavy_hitters = HeavyHitters::new(2, 0.01, 0.01).unwrap();
+        read_items("a\nb\r\na\n\nc\na\n".as_bytes(), &mut heavy_hitters).unwrap();
+        assert_eq!(3, heavy_hitters.estimate(&"a".to_string()));
+        assert_eq!(5, heavy_hitters.sketch().total_count());
+        assert_eq!(("a".to_string(), 3), heavy_hitters.top_k()[0]);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{cell_count, dimensions, row_indexes, CountMinError, HashStrategy};
+
+/// Count-min-sketch which can recover its heavy keys without storing them, for integer keys
+/// Every bucket has a total counter and one counter per key bit, counting the items with that bit set.
+/// If a key has more than half of its bucket's total, the majority of each bit counter gives its bits back.
+/// A key with more than phi of the stream is recovered with 1 - delta confidence when epsilon is at most phi / 2
+#[derive(Clone, Debug)]
+pub struct ReversibleSketch {
+    // every bucket is key_bits + 1 counters, the total first
+    data: Vec<u64>,
+    depth: usize,
+    width: usize,
+    key_bits: u32,
+    hash_strategy: HashStrategy,
+    total: u64,
+}
+
+impl ReversibleSketch {
+    /// Creates a new reversible sketch for the keys of key_bits bits, for instance 32 for IPv4 addresses
+    /// epsilon and delta have the same meaning as in CountMinSketch::new
+    pub fn new(epsilon: f64, delta: f64, key_bits: u32) -> Result<Self, CountMinError> {
+        if key_bits == 0 || key_bits > 64 { return Err(CountMinError::WrongKeyBits); }
+        let (width, depth) = dimensions(epsilon, delta)?;
+        let cells = cell_count(width, depth * (key_bits as usize + 1), std::mem::size_of::<u64>())?;
+        Ok(Self {
+            data: vec![0; cells],
+            depth,
+            width,
+            key_bits,
+            hash_strategy: HashStrategy::default(),
+            total: 0,
+        })
+    }
+
+    /// Sum of all frequencies
+    pub fn total_count(&self) -> u64 {
+        self.total
+    }
+
+    /// Bits above key_bits are ignored
+    pub fn update(&mut self, key: u64, frequency: Option<u64>) {
+        let key = self.mask(key);
+        let frequency = frequency.unwrap_or(1);
+        self.total = self.total.saturating_add(frequency);
+        for bucket in self.buckets(key) {
+            self.data[bucket] = self.data[bucket].saturating_add(frequency);
+            for bit in 0..self.key_bits as usize {
+                if key >> bit & 1 == 1 {
+                    self.data[bucket + 1 + bit] = self.data[bucket + 1 + bit].saturating_add(frequency);
+                }
+            }
+        }
+    }
+
+    pub fn estimate(&self, key: u64) -> u64 {
+        self.buckets(self.mask(key)).map(|bucket| self.data[bucket]).min().unwrap_or(0)
+    }
+
+    /// Keys whose estimated frequency is at least the threshold, the most frequent first
+    /// Keys are decoded from the buckets over the threshold, then checked against their point estimate
+    pub fn heavy_keys(&self, threshold: u64) -> Vec<(u64, u64)> {
+        let threshold = threshold.max(1);
+        let stride = self.key_bits as usize + 1;
+        let mut found: Vec<(u64, u64)> = Vec::new();
+
+        for row in 0..self.depth {
+            for column in 0..self.width {
+                let bucket = ((row * self.width) + column) * stride;
+                let total = self.data[bucket];
+                if total < threshold { continue; }
+
+                // a bit counter is never above its bucket's total, even when they saturate
+                let key = (0..self.key_bits as usize)
+                    .filter(|bit| self.data[bucket + 1 + bit] > total - self.data[bucket + 1 + bit])
+                    .fold(0u64, |key, bit| key | (1 << bit));
+                // a wrong decoding either lands on another bucket or has a low estimate
+                if !self.buckets(key).any(|other| other == bucket) { continue; }
+                let estimate = self.estimate(key);
+                if estimate >= threshold && !found.iter().any(|(other, _)| *other == key) {
+                    found.push((key, estimate));
+                }
+            }
+        }
+        found.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        found
+    }
+
+    fn mask(&self, key: u64) -> u64 {
+        if self.key_bits == 64 { key } else { key & ((1 << self.key_bits) - 1) }
+    }
+
+    // start of the value's bucket on every row
+    fn buckets(&self, key: u64) -> impl Iterator<Item = usize> {
+        let stride = self.key_bits as usize + 1;
+        row_indexes(self.hash_strategy.hash_value(&key), self.width, self.depth).map(move |index| index * stride)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_heavy_keys() {
+        let mut sketch = ReversibleSketch::new(0.01, 0.01, 32).unwrap();
+        for key in 0..2000u64 {
+            sketch.update(key * 7919, None);
+        }
+        sketch.update(0xC0A8_0101, Some(500));
+        sketch.update(0x0A00_0001, Some(300));
+
+        let heavy = sketch.heavy_keys(100);
+        let keys: Vec<u64> = heavy.iter().map(|(key, _)| *key).collect();
+        assert_eq!(vec![0xC0A8_0101, 0x0A00_0001], keys);
+        // estimates may include a few colliding light keys
+        assert!(heavy[0].1 >= 500 && heavy[1].1 >= 300);
+        assert_eq!(2800, sketch.total_count());
+    }
+
+    #[test]
+    fn large_frequencies_saturate() {
+        let mut sketch = ReversibleSketch::new(0.1, 0.1, 16).unwrap();
+        sketch.update(0xBEEF, Some(u64::MAX));
+        assert_eq!(vec![(0xBEEF, u64::MAX)], sketch.heavy_keys(1));
+        sketch.update(0xBEEF, Some(1));
+        assert_eq!(u64::MAX, sketch.total_count());
+        assert_eq!(vec![(0xBEEF, u64::MAX)], sketch.heavy_keys(1));
+    }
+
+    #[test]
+    fn nothing_above_threshold() {
+        let mut sketch = ReversibleSketch::new(0.1, 0.1, 16).unwrap();
+        for key in 0..10u64 {
+            sketch.update(key, None);
+        }
+        assert!(sketch.heavy_keys(50).is_empty());
+    }
+
+    #[test]
+    fn full_width_keys() {
+        let mut sketch = ReversibleSketch::new(0.1, 0.1, 64).unwrap();
+        sketch.update(u64::MAX - 5, Some(10));
+        assert_eq!(vec![(u64::MAX - 5, 10)], sketch.heavy_keys(5));
+        assert!(matches!(ReversibleSketch::new(0.1, 0.1, 65), Err(CountMinError::WrongKeyBits)));
+    }
+}
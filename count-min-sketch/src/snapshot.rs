@@ -0,0 +1,359 @@
+use std::io::{Read, Write};
+
+use crate::counter::Counter;
+use crate::{CountMinError, CountMinSketch, HashStrategy, UpdateMode};
+
+// Snapshot layout, every number is little endian:
+// magic "CMSK" | version u8 | counter bytes u8 | hash strategy u8 | update mode u8
+// width u64 | depth u64 | epsilon f64 | delta f64 | seed u64 | total u64
+// reset interval u64 (0 is none) | updates since reset u64
+// counters, width * depth of them, row by row | xxh3 checksum u64 of everything before it
+const MAGIC: &[u8; 4] = b"CMSK";
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 8 + 8 * 8;
+const CHECKSUM_LENGTH: usize = 8;
+
+// DefaultHasher isn't stable across Rust releases, a snapshot loaded by another build would give wrong estimates
+impl HashStrategy {
+    fn to_code(self) -> Result<u8, CountMinError> {
+        match self {
+            HashStrategy::XxHash => Ok(0),
+            HashStrategy::Fnv => Ok(1),
+            HashStrategy::Default => Err(CountMinError::NonPortableHashStrategy),
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, CountMinError> {
+        match code {
+            0 => Ok(HashStrategy::XxHash),
+            1 => Ok(HashStrategy::Fnv),
+            // written by an older build before Default was rejected
+            2 => Err(CountMinError::NonPortableHashStrategy),
+            _ => Err(CountMinError::CorruptedSnapshot("unknown hash strategy")),
+        }
+    }
+}
+
+impl UpdateMode {
+    fn to_code(self) -> u8 {
+        match self {
+            UpdateMode::Standard => 0,
+            UpdateMode::Conservative => 1,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, CountMinError> {
+        match code {
+            0 => Ok(UpdateMode::Standard),
+            1 => Ok(UpdateMode::Conservative),
+            _ => Err(CountMinError::CorruptedSnapshot("unknown update mode")),
+        }
+    }
+}
+
+// reads the header fields in order
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn u8(&mut self) -> u8 {
+        self.position += 1;
+        self.bytes[self.position - 1]
+    }
+
+    fn u64(&mut self) -> u64 {
+        self.position += 8;
+        u64::from_le_bytes(self.bytes[self.position - 8..self.position].try_into().unwrap())
+    }
+
+    fn f64(&mut self) -> f64 {
+        f64::from_bits(self.u64())
+    }
+}
+
+// number of counters a decoded sketch must have
+fn counter_count(width: usize, depth: usize) -> Result<usize, CountMinError> {
+    if width == 0 || depth == 0 { return Err(CountMinError::CorruptedSnapshot("empty dimensions")); }
+    width.checked_mul(depth).ok_or(CountMinError::CorruptedSnapshot("dimensions overflow"))
+}
+
+// epsilon may be above 1 for the very narrow sketches, delta is 0 for the very deep ones
+fn check_error_rates(epsilon: f64, delta: f64) -> Result<(), CountMinError> {
+    if !(epsilon.is_finite() && epsilon > 0.0 && (0.0..1.0).contains(&delta)) {
+        return Err(CountMinError::CorruptedSnapshot("epsilon or delta is out of range"));
+    }
+    Ok(())
+}
+
+/// Serializes the hash strategy, rejecting HashStrategy::Default like the binary snapshots
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_hash_strategy<S: serde::Serializer>(hash_strategy: &HashStrategy, serializer: S) -> Result<S::Ok, S::Error> {
+    hash_strategy.to_code().map_err(serde::ser::Error::custom)?;
+    serde::Serialize::serialize(hash_strategy, serializer)
+}
+
+/// Fields of a deserialized sketch before they are checked, see CountMinSketch's Deserialize
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+pub(crate) struct RawCountMinSketch<C> {
+    data: Vec<C>,
+    depth: usize,
+    width: usize,
+    epsilon: f64,
+    delta: f64,
+    mode: UpdateMode,
+    hash_strategy: HashStrategy,
+    seed: u64,
+    total: u64,
+    reset_interval: Option<u64>,
+    updates_since_reset: u64,
+}
+
+// deserialized sketches get the same checks as the binary snapshots
+#[cfg(feature = "serde")]
+impl<C: Counter> TryFrom<RawCountMinSketch<C>> for CountMinSketch<C> {
+    type Error = CountMinError;
+
+    fn try_from(raw: RawCountMinSketch<C>) -> Result<Self, Self::Error> {
+        if raw.data.len() != counter_count(raw.width, raw.depth)? {
+            return Err(CountMinError::CorruptedSnapshot("counter count doesn't match the dimensions"));
+        }
+        raw.hash_strategy.to_code()?;
+        check_error_rates(raw.epsilon, raw.delta)?;
+        if raw.reset_interval == Some(0) {
+            return Err(CountMinError::CorruptedSnapshot("reset interval must be positive"));
+        }
+
+        let mut sketch = Self::from_parts(raw.width, raw.depth, raw.epsilon, raw.delta, raw.mode, raw.hash_strategy);
+        sketch.data = raw.data;
+        sketch.seed = raw.seed;
+        sketch.total = raw.total;
+        sketch.reset_interval = raw.reset_interval;
+        sketch.updates_since_reset = raw.updates_since_reset;
+        Ok(sketch)
+    }
+}
+
+impl<C: Counter> CountMinSketch<C> {
+    /// Encodes the sketch into the versioned binary snapshot format
+    /// Sketches using HashStrategy::Default can't be encoded, their hashes aren't portable
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CountMinError> {
+        let hash_strategy = self.hash_strategy.to_code()?;
+        let mut output = Vec::with_capacity(HEADER_LENGTH + self.data.len() * C::BYTES + CHECKSUM_LENGTH);
+        output.extend_from_slice(MAGIC);
+        output.push(VERSION);
+        output.push(C::BYTES as u8);
+        output.push(hash_strategy);
+        output.push(self.mode.to_code());
+        for field in [self.width as u64, self.depth as u64, self.epsilon.to_bits(), self.delta.to_bits(),
+            self.seed, self.total, self.reset_interval.unwrap_or(0), self.updates_since_reset] {
+            output.extend_from_slice(&field.to_le_bytes());
+        }
+        for cell in self.data.iter() {
+            cell.write_le(&mut output);
+        }
+        let checksum = xxhash_rust::xxh3::xxh3_64(&output);
+        output.extend_from_slice(&checksum.to_le_bytes());
+        Ok(output)
+    }
+
+    /// Decodes a snapshot created by to_bytes
+    /// The counter type must be the same one the snapshot was created with
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CountMinError> {
+        if bytes.len() < HEADER_LENGTH + CHECKSUM_LENGTH { return Err(CountMinError::CorruptedSnapshot("too short")); }
+        if &bytes[0..4] != MAGIC { return Err(CountMinError::CorruptedSnapshot("not a count-min-sketch snapshot")); }
+
+        let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LENGTH);
+        if xxhash_rust::xxh3::xxh3_64(content).to_le_bytes() != checksum {
+            return Err(CountMinError::CorruptedSnapshot("checksum mismatch"));
+        }
+
+        let mut cursor = Cursor { bytes: content, position: 4 };
+        let version = cursor.u8();
+        if version != VERSION { return Err(CountMinError::UnsupportedVersion(version)); }
+        let counter_bytes = cursor.u8() as usize;
+        if counter_bytes != C::BYTES {
+            return Err(CountMinError::CounterMismatch { expected: C::BYTES, found: counter_bytes });
+        }
+        let hash_strategy = HashStrategy::from_code(cursor.u8())?;
+        let mode = UpdateMode::from_code(cursor.u8())?;
+        let width = cursor.u64() as usize;
+        let depth = cursor.u64() as usize;
+        let epsilon = cursor.f64();
+        let delta = cursor.f64();
+        let seed = cursor.u64();
+        let total = cursor.u64();
+        let reset_interval = cursor.u64();
+        let updates_since_reset = cursor.u64();
+
+        check_error_rates(epsilon, delta)?;
+        let cells = counter_count(width, depth)?;
+        if Some(content.len() - HEADER_LENGTH) != cells.checked_mul(C::BYTES) {
+            return Err(CountMinError::CorruptedSnapshot("counter count doesn't match the dimensions"));
+        }
+
+        let mut sketch = Self::from_parts(width, depth, epsilon, delta, mode, hash_strategy);
+        sketch.data = content[HEADER_LENGTH..].chunks_exact(C::BYTES).map(C::read_le).collect();
+        sketch.seed = seed;
+        sketch.total = total;
+        sketch.set_reset_interval(Some(reset_interval));
+        sketch.updates_since_reset = updates_since_reset;
+        Ok(sketch)
+    }
+
+    /// Writes the snapshot into the writer, for instance a file
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), CountMinError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Reads a whole snapshot from the reader
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, CountMinError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled_sketch() -> CountMinSketch {
+        let mut sketch = CountMinSketch::builder()
+            .error_rate(0.1, 0.05)
+            .mode(UpdateMode::Conservative)
+            .hash_strategy(HashStrategy::Fnv)
+            .seed(42)
+            .reset_after(1000)
+            .build()
+            .unwrap();
+        for i in 0..100u64 { sketch.update(i, Some(i)); }
+        sketch
+    }
+
+    #[test]
+    fn round_trip() {
+        let sketch = filled_sketch();
+        let restored: CountMinSketch = CountMinSketch::from_bytes(&sketch.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(sketch.width(), restored.width());
+        assert_eq!(sketch.depth(), restored.depth());
+        assert_eq!(sketch.epsilon(), restored.epsilon());
+        assert_eq!(sketch.delta(), restored.delta());
+        assert_eq!(UpdateMode::Conservative, restored.mode());
+        assert_eq!(HashStrategy::Fnv, restored.hash_strategy());
+        assert_eq!(42, restored.seed());
+        assert_eq!(Some(1000), restored.reset_interval());
+        assert_eq!(sketch.total_count(), restored.total_count());
+        for i in 0..100u64 {
+            assert_eq!(sketch.estimate(i), restored.estimate(i));
+        }
+    }
+
+    #[test]
+    fn round_trip_through_writer() {
+        let mut sketch = CountMinSketch::builder().dimensions(50, 3).counter::<u16>().build().unwrap();
+        sketch.update("a", Some(7));
+
+        let mut file = Vec::new();
+        sketch.write_to(&mut file).unwrap();
+        let restored = CountMinSketch::<u16>::read_from(file.as_slice()).unwrap();
+        assert_eq!(7, restored.estimate("a"));
+        assert_eq!(None, restored.reset_interval());
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_rejected() {
+        let bytes = filled_sketch().to_bytes().unwrap();
+
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LENGTH + 3] ^= 1;
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&flipped), Err(CountMinError::CorruptedSnapshot(_))));
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&wrong_magic), Err(CountMinError::CorruptedSnapshot(_))));
+
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&bytes[..bytes.len() - 1]), Err(CountMinError::CorruptedSnapshot(_))));
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&bytes[..10]), Err(CountMinError::CorruptedSnapshot(_))));
+    }
+
+    #[test]
+    fn mismatched_headers_are_rejected() {
+        let bytes = filled_sketch().to_bytes().unwrap();
+        assert!(matches!(CountMinSketch::<u32>::from_bytes(&bytes),
+            Err(CountMinError::CounterMismatch { expected: 4, found: 8 })));
+
+        // a newer version with a valid checksum
+        let newer = resealed(&bytes, |content| content[4] = VERSION + 1);
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&newer), Err(CountMinError::UnsupportedVersion(2))));
+    }
+
+    // changes the snapshot content, then puts a valid checksum after it
+    fn resealed(bytes: &[u8], change: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut content = bytes[..bytes.len() - CHECKSUM_LENGTH].to_vec();
+        change(&mut content);
+        let checksum = xxhash_rust::xxh3::xxh3_64(&content);
+        content.extend_from_slice(&checksum.to_le_bytes());
+        content
+    }
+
+    #[test]
+    fn wrong_error_rates_are_rejected() {
+        let bytes = filled_sketch().to_bytes().unwrap();
+        // epsilon is right after the width and the depth, delta follows it
+        let nan_epsilon = resealed(&bytes, |content| content[24..32].copy_from_slice(&f64::NAN.to_bits().to_le_bytes()));
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&nan_epsilon), Err(CountMinError::CorruptedSnapshot(_))));
+        let wrong_delta = resealed(&bytes, |content| content[32..40].copy_from_slice(&1.5f64.to_bits().to_le_bytes()));
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&wrong_delta), Err(CountMinError::CorruptedSnapshot(_))));
+    }
+
+    #[test]
+    fn default_hash_strategy_is_not_portable() {
+        let sketch = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::Default).unwrap();
+        assert!(matches!(sketch.to_bytes(), Err(CountMinError::NonPortableHashStrategy)));
+        assert!(matches!(sketch.write_to(Vec::new()), Err(CountMinError::NonPortableHashStrategy)));
+
+        // a snapshot which claims the default hasher, with a valid checksum
+        let bytes = resealed(&filled_sketch().to_bytes().unwrap(), |content| content[6] = 2);
+        assert!(matches!(CountMinSketch::<u64>::from_bytes(&bytes), Err(CountMinError::NonPortableHashStrategy)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let sketch = filled_sketch();
+        let json = serde_json::to_string(&sketch).unwrap();
+        let restored: CountMinSketch = serde_json::from_str(&json).unwrap();
+        assert_eq!(42, restored.seed());
+        for i in 0..100u64 {
+            assert_eq!(sketch.estimate(i), restored.estimate(i));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tampered_json_is_rejected() {
+        let json = serde_json::to_value(filled_sketch()).unwrap();
+        let tamper = |field: &str, value: serde_json::Value| {
+            let mut tampered = json.clone();
+            tampered[field] = value;
+            serde_json::from_value::<CountMinSketch>(tampered)
+        };
+
+        assert!(tamper("data", serde_json::json!([1, 2, 3])).is_err());
+        assert!(tamper("width", serde_json::json!(0)).is_err());
+        assert!(tamper("depth", serde_json::json!(0)).is_err());
+        assert!(tamper("reset_interval", serde_json::json!(0)).is_err());
+        assert!(tamper("hash_strategy", serde_json::json!("Default")).is_err());
+        assert!(tamper("epsilon", serde_json::json!(-0.1)).is_err());
+        assert!(tamper("delta", serde_json::json!(1.5)).is_err());
+
+        let default_hasher = CountMinSketch::with_options(0.1, 0.1, UpdateMode::Standard, HashStrategy::Default).unwrap();
+        assert!(serde_json::to_string(&default_hasher).is_err());
+        assert!(tamper("reset_interval", serde_json::Value::Null).is_ok());
+    }
+}
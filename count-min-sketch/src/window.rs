@@ -0,0 +1,138 @@
+use std::hash::Hash;
+
+use crate::{CountMinError, CountMinSketch};
+
+/// Count-min-sketch which only remembers the last few ticks
+/// It's a ring of sub-sketches, one per tick. Updates go to the current tick's sketch,
+/// advance moves to the next one and forgets the oldest tick.
+/// What a tick means (a second, a minute, a batch) is up to the caller, so it's testable without a clock
+#[derive(Clone, Debug)]
+pub struct WindowedCountMinSketch {
+    sketches: Vec<CountMinSketch>,
+    current: usize,
+}
+
+impl WindowedCountMinSketch {
+    /// Creates a new windowed sketch which remembers the given number of ticks
+    /// epsilon and delta are passed to every sub-sketch
+    pub fn new(epsilon: f64, delta: f64, window: usize) -> Result<Self, CountMinError> {
+        Self::with_sketch(CountMinSketch::new(epsilon, delta)?, window)
+    }
+
+    /// Creates a new windowed sketch, every tick uses an empty copy of the given sketch
+    pub fn with_sketch(mut sketch: CountMinSketch, window: usize) -> Result<Self, CountMinError> {
+        if window == 0 { return Err(CountMinError::EmptyWindow); }
+        sketch.clear();
+        Ok(Self {
+            sketches: vec![sketch; window],
+            current: 0,
+        })
+    }
+
+    /// Number of ticks the sketch remembers
+    pub fn window(&self) -> usize {
+        self.sketches.len()
+    }
+
+    /// Updates the current tick
+    pub fn update<T>(&mut self, value: T, frequency: Option<u64>) where T : Hash {
+        self.sketches[self.current].update(value, frequency);
+    }
+
+    /// Moves to the next tick, the oldest tick is forgotten
+    pub fn advance(&mut self) {
+        self.advance_by(1);
+    }
+
+    /// Moves the given number of ticks forward
+    pub fn advance_by(&mut self, ticks: usize) {
+        for _ in 0..ticks.min(self.sketches.len()) {
+            self.current = (self.current + 1) % self.sketches.len();
+            self.sketches[self.current].clear();
+        }
+    }
+
+    /// Estimated frequency within the whole window
+    pub fn estimate<T>(&self, value: T) -> u64 where T : Hash {
+        self.estimate_last(value, self.sketches.len())
+    }
+
+    /// Estimated frequency within the last given ticks, the current tick included
+    /// ticks more than the window are treated as the whole window
+    pub fn estimate_last<T>(&self, value: T, ticks: usize) -> u64 where T : Hash {
+        let ticks = ticks.min(self.sketches.len());
+        if ticks == 0 { return 0; }
+        let length = self.sketches.len();
+        let first = &self.sketches[self.current];
+
+        // summing the same cell over the ticks before taking the minimum is tighter than summing the minimums
+        let mut smallest = u64::MAX;
+        for index in first.indexes(&value) {
+            let count: u64 = (0..ticks)
+                .map(|tick| self.sketches[(self.current + length - tick) % length].data[index])
+                .fold(0, u64::saturating_add);
+            if count < smallest { smallest = count; }
+        }
+        smallest
+    }
+
+    /// Merges the whole window into a single sketch
+    pub fn snapshot(&self) -> CountMinSketch {
+        let mut result = self.sketches[self.current].clone();
+        for (tick, sketch) in self.sketches.iter().enumerate() {
+            if tick != self.current {
+                // every sub-sketch has the same configuration
+                result.merge(sketch).expect("sub-sketches are compatible");
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_ticks_are_forgotten() {
+        let mut sketch = WindowedCountMinSketch::new(0.1, 0.1, 3).unwrap();
+        sketch.update(5, Some(4));
+        sketch.advance();
+        sketch.update(5, Some(2));
+        assert_eq!(6, sketch.estimate(5));
+
+        sketch.advance_by(2);
+        assert_eq!(2, sketch.estimate(5));
+        sketch.advance();
+        assert_eq!(0, sketch.estimate(5));
+    }
+
+    #[test]
+    fn estimate_last_ticks() {
+        let mut sketch = WindowedCountMinSketch::new(0.1, 0.1, 5).unwrap();
+        for tick in 1..=5 {
+            sketch.update("a", Some(tick));
+            if tick < 5 { sketch.advance(); }
+        }
+        assert_eq!(5, sketch.estimate_last("a", 1));
+        assert_eq!(9, sketch.estimate_last("a", 2));
+        assert_eq!(15, sketch.estimate_last("a", 10));
+        assert_eq!(0, sketch.estimate_last("a", 0));
+        assert_eq!(15, sketch.snapshot().estimate("a"));
+    }
+
+    #[test]
+    fn window_sum_saturates() {
+        let mut sketch = WindowedCountMinSketch::new(0.1, 0.1, 2).unwrap();
+        sketch.update(1, Some(u64::MAX));
+        sketch.advance();
+        sketch.update(1, Some(1));
+        assert_eq!(u64::MAX, sketch.estimate(1));
+        assert_eq!(u64::MAX, sketch.snapshot().estimate(1));
+    }
+
+    #[test]
+    fn empty_window_is_rejected() {
+        assert!(matches!(WindowedCountMinSketch::new(0.1, 0.1, 0), Err(CountMinError::EmptyWindow)));
+    }
+}
@@ -0,0 +1,56 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// f64 priority with a total order, so it can be used in the heap
+/// Follows f64::total_cmp: -NaN < -inf < ... < -0.0 < 0.0 < ... < inf < NaN
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl From<f64> for TotalF64 {
+    fn from(value: f64) -> Self {
+        TotalF64(value)
+    }
+}
+
+impl Display for TotalF64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_order() {
+        let mut values = [TotalF64(f64::NAN), TotalF64(1.5), TotalF64(f64::NEG_INFINITY), TotalF64(-0.0), TotalF64(0.0)];
+        values.sort();
+        let sorted: Vec<f64> = values.iter().map(|value| value.0).collect();
+        assert_eq!(f64::NEG_INFINITY, sorted[0]);
+        assert!(sorted[1].is_sign_negative() && sorted[1] == 0.0);
+        assert!(sorted[2].is_sign_positive() && sorted[2] == 0.0);
+        assert_eq!(1.5, sorted[3]);
+        assert!(sorted[4].is_nan());
+    }
+}
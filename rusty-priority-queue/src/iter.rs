@@ -0,0 +1,55 @@
+use std::hash::Hash;
+use std::iter::FusedIterator;
+
+use crate::DHeap;
+use crate::pair::Pair;
+
+/// Iterator which takes the pairs out of the heap in priority order, see DHeap::drain_sorted
+#[derive(Debug)]
+pub struct DrainSorted<'a, T: Eq + Hash, P: Ord> {
+    heap: &'a mut DHeap<T, P>,
+}
+
+impl<'a, T: Eq + Hash, P: Ord> DrainSorted<'a, T, P> {
+    pub(crate) fn new(heap: &'a mut DHeap<T, P>) -> Self {
+        Self { heap }
+    }
+}
+
+impl<T: Eq + Hash, P: Ord> Iterator for DrainSorted<'_, T, P> {
+    type Item = Pair<T, P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.heap.top()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T: Eq + Hash, P: Ord> ExactSizeIterator for DrainSorted<'_, T, P> {}
+
+impl<T: Eq + Hash, P: Ord> FusedIterator for DrainSorted<'_, T, P> {}
+
+impl<T: Eq + Hash, P: Ord> Drop for DrainSorted<'_, T, P> {
+    fn drop(&mut self) {
+        self.heap.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drains_in_priority_order() {
+        let mut heap: DHeap<&str> = [Pair::new("b", 2), Pair::new("c", 3), Pair::new("a", 1)].into_iter().collect();
+        let mut drain = heap.drain_sorted();
+        assert_eq!(3, drain.len());
+        assert_eq!(3, drain.next().unwrap().priority);
+        drop(drain);
+        assert!(heap.is_empty());
+        assert!(!heap.contains(&"a"));
+    }
+}
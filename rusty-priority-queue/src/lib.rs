@@ -1,98 +1,127 @@
-use std::{fmt::Display, collections::HashMap};
+use std::borrow::Borrow;
 use std::hash::Hash;
 use anyhow::Result;
 use thiserror::Error;
 
 use pair::Pair;
+use order::HeapOrder;
+use iter::DrainSorted;
+use slots::SlotIndex;
 
 pub mod pair;
+pub mod float;
+pub mod order;
+pub mod iter;
+mod slots;
 
 #[derive(Error, Debug)]
 pub enum DHeapError {
     #[error("Element already exists in the heap")]
     ElementAlreadyExists,
-    #[error("usize max value is not available for priority")]
-    UnavailablePriority
+    #[error("Element doesn't exist in the heap")]
+    ElementNotFound,
+    #[error("New priority is lower than the current one")]
+    PriorityNotIncreased,
+    #[error("New priority is higher than the current one")]
+    PriorityNotDecreased,
 }
 
 
+/// d-ary heap, the pair with the highest priority is on the top
+/// Priorities can be any ordered type, usize by default. For floats see float::TotalF64
+/// What highest means is decided by the heap order, the largest priority by default
+/// Elements are never cloned, each one is kept only in its pair
 #[derive(Debug)]
-pub struct DHeap<T: Eq + Hash + Clone + Display + PartialEq> {
-    data: Vec<Pair<T>>,
+pub struct DHeap<T: Eq + Hash, P: Ord = usize> {
+    data: Vec<Pair<T, P>>,
+    // hash of the element on the same index in data, moves together with the pair
+    hashes: Vec<u64>,
     branching_factor: usize,
-    map: HashMap<T, bool>
+    // finds the index of an element in data, kept up to date on every swap
+    slots: SlotIndex,
+    order: HeapOrder<P>
 }
 
-impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
+impl<T: Eq + Hash, P: Ord> DHeap<T, P> {
     /// Creates a new heap
     pub fn new(initial_capacity: Option<usize>, branching_factor: Option<usize>) -> Self {
-        match initial_capacity {
-            Some(v) => DHeap { data: Vec::with_capacity(v), 
-                branching_factor: branching_factor.unwrap_or(4),
-                map: HashMap::with_capacity(v)},
-            None => DHeap { data: Vec::new(),
-                branching_factor: branching_factor.unwrap_or(4),
-                map: HashMap::new()},
-        }
+        Self::with_order(initial_capacity, branching_factor, HeapOrder::Max)
     }
 
-    /// Accepts a slice of pairs and creates a heap
-    pub fn with_pairs(data: &[Pair<T>], initial_capacity: Option<usize>, branching_factor: Option<usize>) -> Result<Self> {
-        if data.iter().any(|x| x.priority == std::usize::MAX) { return Err(anyhow::Error::new(DHeapError::UnavailablePriority)); }
+    /// Creates a new heap with the given order, for instance HeapOrder::Min for a min-heap
+    pub fn with_order(initial_capacity: Option<usize>, branching_factor: Option<usize>, order: HeapOrder<P>) -> Self {
+        let capacity = initial_capacity.unwrap_or(0);
+        DHeap { data: Vec::with_capacity(capacity),
+            hashes: Vec::with_capacity(capacity),
+            branching_factor: branching_factor.unwrap_or(4),
+            slots: SlotIndex::with_capacity(capacity),
+            order}
+    }
 
-        let capacity = if let Some(capacity) = initial_capacity {
-            if capacity > data.len() { capacity } else { data.len() * 2 }
-            } else { data.len() * 2 };
-        
-        let mut heap = DHeap { data: Vec::with_capacity(capacity), 
-                branching_factor: branching_factor.unwrap_or(4),
-                map: HashMap::with_capacity(capacity)};
-        heap.map = data.iter().map(|x| (x.get_cloned_element(), true)).collect::<HashMap<T, bool>>();
-        heap.data = Vec::from(data);
-        heap.heapify();
-            
-        Ok(heap)
+    /// Returns the order which is selected while creating the heap
+    pub fn order(&self) -> HeapOrder<P> {
+        self.order
     }
 
-    /// Returns if the element exists in the heap
-    pub fn contains(&self, element: &T) -> bool {
-        if self.map.contains_key(element) { true }
-        else { false }
+    /// Number of pairs in the heap
+    pub fn len(&self) -> usize {
+        self.data.len()
     }
 
-    /// Removes the element from the heap
-    // Essentially we're just updating its priority to the max, then pop
-    // Due to that, we shouldn't allow max usize priority while inserting
-    pub fn remove(&mut self, element: T) -> Option<Pair<T>> {
-        if !self.map.contains_key(&element) { return None; }
-        self.map.remove(&element);
+    /// Returns true if the heap has no pairs
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
 
-        self.update_priority(element, std::usize::MAX);
-        self.top()
+    /// Removes all the pairs, capacity is kept
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.hashes.clear();
+        self.slots.clear();
     }
 
-    /// Inserts the value
-    pub fn insert_value(&mut self, element: T, priority: usize) -> Result<(), anyhow::Error> {
-        if self.map.contains_key(&element) { return Err(anyhow::Error::new(DHeapError::ElementAlreadyExists)); }
-        if priority == std::usize::MAX { return Err(anyhow::Error::new(DHeapError::UnavailablePriority)); }
+    /// Iterates over the pairs in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, Pair<T, P>> {
+        self.data.iter()
+    }
 
-        self.map.insert(element.clone(), true);
-        
-        let pair = Pair::new(element, priority);
-        self.data.push(pair);
-        self.bubble_up(None);
+    /// Consumes the heap and returns the pairs, the highest priority first
+    pub fn into_sorted_vec(mut self) -> Vec<Pair<T, P>> {
+        let mut sorted = Vec::with_capacity(self.data.len());
+        while let Some(pair) = self.top() {
+            sorted.push(pair);
+        }
+        sorted
+    }
 
-        Ok(())
+    /// Takes the pairs out of the heap, the highest priority first
+    /// Pairs which are not iterated are removed when the iterator is dropped
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, P> {
+        DrainSorted::new(self)
     }
 
-    /// Inserts a pair
-    pub fn insert_pair(&mut self, element: Pair<T>) -> Result<(), anyhow::Error> {
-        if self.map.contains_key(&element.get_element()) { return Err(anyhow::Error::new(DHeapError::ElementAlreadyExists)); }
-        if element.priority == std::usize::MAX { return Err(anyhow::Error::new(DHeapError::UnavailablePriority)); }
+    /// Returns if the element exists in the heap
+    /// Like HashMap, any borrowed form of the element can be used, e.g. &str for String elements
+    pub fn contains<Q: Hash + Eq + ?Sized>(&self, element: &Q) -> bool where T: Borrow<Q> {
+        self.find_index(element).is_some()
+    }
 
-        self.map.insert(element.get_cloned_element(), true);
+    /// Removes the element from the heap
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, element: &Q) -> Option<Pair<T, P>> where T: Borrow<Q> {
+        let index = self.find_index(element)?;
+        self.remove_at(index)
+    }
+
+    /// Inserts the value
+    pub fn insert_value(&mut self, element: T, priority: P) -> Result<(), anyhow::Error> {
+        self.insert_pair(Pair::new(element, priority))
+    }
+
+    /// Inserts a pair
+    pub fn insert_pair(&mut self, element: Pair<T, P>) -> Result<(), anyhow::Error> {
+        if self.contains(element.get_element()) { return Err(anyhow::Error::new(DHeapError::ElementAlreadyExists)); }
 
-        self.data.push(element);
+        self.push_unordered(element);
         self.bubble_up(None);
 
         Ok(())
@@ -100,8 +129,8 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
 
     /// Returns the highest priority value without taking it out of the queue
     /// If empty, returns None
-    pub fn peek(&self) -> Option<&Pair<T>> {
-        if self.data.len() == 0 {
+    pub fn peek(&self) -> Option<&Pair<T, P>> {
+        if self.data.is_empty() {
             None
         } else {
             Some(&self.data[0])
@@ -110,60 +139,83 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
     
     /// Returns the highest priority value. This operation take the value out of the queue
     /// If empty, returns None
-    pub fn top(&mut self) -> Option<Pair<T>> {
-        let last_element = self.remove_last()?;
-        if self.data.is_empty() {
-            self.map.remove(last_element.get_element());
-            Some(last_element)
-        } else {
-            let root_element = self.data[0].clone();
-            self.data[0] = last_element;
-            self.push_down_optimized(None);
-            self.map.remove(root_element.get_element());
-            Some(root_element)
-        }
+    pub fn top(&mut self) -> Option<Pair<T, P>> {
+        self.remove_at(0)
     }
 
     /// Finds and update priority of the value
-    pub fn update_priority(&mut self, old_value: T, new_priority: usize) {
-        if let Some(index) = self.find_index(old_value) {
-            let temp = self.data[index].clone();
-            self.data.remove(index);
-            let updated_pair = Pair::new(temp.get_cloned_element(), new_priority);
-            self.insert_pair_for_update(updated_pair);
+    pub fn update_priority<Q: Hash + Eq + ?Sized>(&mut self, element: &Q, new_priority: P) where T: Borrow<Q> {
+        if let Some(index) = self.find_index(element) {
+            self.data[index].priority = new_priority;
+            self.sift(index);
         }
     }
 
-    fn insert_pair_for_update(&mut self, element: Pair<T>) {
-        self.data.push(element);
-        self.bubble_up(None);
+    /// Sets a priority which is not lower than the current one
+    /// Lower and higher are decided by the priority type, not by the heap order
+    pub fn increase_priority<Q: Hash + Eq + ?Sized>(&mut self, element: &Q, new_priority: P) -> Result<()> where T: Borrow<Q> {
+        let index = self.find_index(element).ok_or(DHeapError::ElementNotFound)?;
+        if new_priority < self.data[index].priority { return Err(anyhow::Error::new(DHeapError::PriorityNotIncreased)); }
+
+        self.data[index].priority = new_priority;
+        self.sift(index);
+        Ok(())
     }
 
-    fn heapify(&mut self)
-    {
-        let mut max_index = (self.data.len() - 1) / self.branching_factor;
-        while max_index != 0 {
-            self.push_down_optimized(Some(max_index));
-            max_index -= 1;
-        }
-        self.push_down_optimized(None);
+    /// Sets a priority which is not higher than the current one
+    pub fn decrease_priority<Q: Hash + Eq + ?Sized>(&mut self, element: &Q, new_priority: P) -> Result<()> where T: Borrow<Q> {
+        let index = self.find_index(element).ok_or(DHeapError::ElementNotFound)?;
+        if new_priority > self.data[index].priority { return Err(anyhow::Error::new(DHeapError::PriorityNotDecreased)); }
+
+        self.data[index].priority = new_priority;
+        self.sift(index);
+        Ok(())
     }
 
-    fn find_index(&self, old_value: T) -> Option<usize> {
-        for (index, pair) in self.data.iter().enumerate() {
-            if *pair.get_element() == old_value {
-                return Some(index);
-            }
+    fn find_index<Q: Hash + Eq + ?Sized>(&self, element: &Q) -> Option<usize> where T: Borrow<Q> {
+        let hash = self.slots.hash(element);
+        self.slots.get(hash).iter().copied()
+            .find(|&index| self.data[index].get_element().borrow() == element)
+    }
+
+    // adds the pair to the end, the caller restores the heap order
+    fn push_unordered(&mut self, pair: Pair<T, P>) {
+        let hash = self.slots.hash(pair.get_element());
+        self.slots.insert(hash, self.data.len());
+        self.hashes.push(hash);
+        self.data.push(pair);
+    }
+
+    // The last pair takes its place, then it's moved up or down to where it belongs
+    fn remove_at(&mut self, index: usize) -> Option<Pair<T, P>> {
+        if index >= self.data.len() { return None; }
+
+        let last_index = self.data.len() - 1;
+        self.swap(index, last_index);
+        let removed = self.data.pop()?;
+        let hash = self.hashes.pop()?;
+        self.slots.remove(hash, last_index);
+        if index < self.data.len() {
+            self.sift(index);
         }
-        None
+        Some(removed)
     }
 
-    fn remove_last(&mut self) -> Option<Pair<T>> {
-        if self.data.is_empty() {
-            None
-        } else {
-            self.data.pop()
+    // moves the pair on the index up or down to where it belongs, only one of them does anything
+    fn sift(&mut self, index: usize) {
+        self.bubble_up(Some(index));
+        self.push_down(Some(index));
+    }
+
+    fn heapify(&mut self)
+    {
+        if self.data.is_empty() { return; }
+        let mut max_index = (self.data.len() - 1) / self.branching_factor;
+        while max_index != 0 {
+            self.push_down(Some(max_index));
+            max_index -= 1;
         }
+        self.push_down(None);
     }
 
     // bubbles up the selected element
@@ -173,7 +225,7 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
         while parent_index > 0 {
             let current_index = parent_index;
             parent_index = self.get_parent_index(parent_index);
-            if self.data[parent_index].priority < self.data[current_index].priority {
+            if self.is_higher(current_index, parent_index) {
                 self.swap(current_index, parent_index)
             } else {
                 break;
@@ -181,53 +233,23 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
         }
     }
 
-    #[allow(dead_code)]
-    fn bubble_up_optimized(&mut self, initial_index: Option<usize>) {
-        let mut index = initial_index.unwrap_or(self.data.len() - 1);
-        let current = self.data[index].clone();
-        while index > 0 {
-            let parent_index = self.get_parent_index(index);
-            if self.data[parent_index].priority < self.data[index].priority {
-                self.data[index] = self.data[parent_index].clone();
-                index = parent_index;
-            } else {
-                break;
-            }
-        }
-        self.data[index] = current;
-    }
-
-    #[allow(dead_code)]
+    // pairs are swapped instead of shifting a copy of the pushed one down, so elements don't need to be cloned
     fn push_down(&mut self, initial_index: Option<usize>) {
-        let index = initial_index.unwrap_or(0);
-        let mut current_index = index;
+        let mut current_index = initial_index.unwrap_or(0);
         while current_index < self.first_leaf_index() {
-            let highest_priority_child_index = self.highest_priority_child_index(index);
-            if self.data[current_index].priority < self.data[highest_priority_child_index].priority {
-                self.swap(current_index,highest_priority_child_index);
+            let highest_priority_child_index = self.highest_priority_child_index(current_index);
+            if self.is_higher(highest_priority_child_index, current_index) {
+                self.swap(current_index, highest_priority_child_index);
                 current_index = highest_priority_child_index;
             } else {
                 break;
             }
-        }    
-    }
-
-    fn push_down_optimized(&mut self, initial_index: Option<usize>) {
-        let mut index = initial_index.unwrap_or(0);
-        let current = self.data[index].clone();
-        while index < self.first_leaf_index() {
-            let highest_priority_child_index = self.highest_priority_child_index(index);
-            if self.data[index].priority < self.data[highest_priority_child_index].priority {
-                self.data[index] = self.data[highest_priority_child_index].clone();
-                index = highest_priority_child_index;
-            } else {
-                break;
-            }
-        } 
-        self.data[index] = current;
+        }
     }
 
     fn first_leaf_index(&self) -> usize {
+        // a single element is a leaf itself
+        if self.data.len() < 2 { return 0; }
         (self.data.len() - 2) / self.branching_factor + 1
     }
 
@@ -236,7 +258,16 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
     }
 
     fn swap(&mut self, first_index: usize, second_index: usize) {
+        if first_index == second_index { return; }
         self.data.swap(first_index, second_index);
+        self.hashes.swap(first_index, second_index);
+        self.slots.relocate(self.hashes[first_index], second_index, first_index);
+        self.slots.relocate(self.hashes[second_index], first_index, second_index);
+    }
+
+    // true if the pair on the first index should be above the one on the second index
+    fn is_higher(&self, first_index: usize, second_index: usize) -> bool {
+        self.order.is_higher(&self.data[first_index].priority, &self.data[second_index].priority)
     }
 
     fn highest_priority_child_index(&self, index: usize) -> usize {
@@ -246,14 +277,14 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
             return index;
         }
 
-        let mut highest_priority_index = index;
-        for i in 1..=self.branching_factor {
+        let mut highest_priority_index = first_child_index;
+        for i in 2..=self.branching_factor {
             let child_index = (self.branching_factor * index) + i;
             if self.data.len() - 1 < child_index {
                 continue;
             }
 
-            if self.data[child_index].priority > self.data[highest_priority_index].priority {
+            if self.is_higher(child_index, highest_priority_index) {
                 highest_priority_index = child_index;
             }
         }
@@ -261,10 +292,97 @@ impl<T: Eq + Hash + Clone + Display + PartialEq> DHeap<T> {
     }
 }
 
+impl<T: Eq + Hash + Clone, P: Ord + Clone> DHeap<T, P> {
+    /// Accepts a slice of pairs and creates a heap
+    /// Elements must be unique. Pairs are cloned, collect an iterator of pairs to move them instead
+    pub fn with_pairs(data: &[Pair<T, P>], initial_capacity: Option<usize>, branching_factor: Option<usize>) -> Result<Self> {
+        Self::with_pairs_and_order(data, initial_capacity, branching_factor, HeapOrder::Max)
+    }
+
+    /// Accepts a slice of pairs and creates a heap with the given order
+    pub fn with_pairs_and_order(data: &[Pair<T, P>], initial_capacity: Option<usize>, branching_factor: Option<usize>,
+        order: HeapOrder<P>) -> Result<Self> {
+        let capacity = if let Some(capacity) = initial_capacity {
+            if capacity > data.len() { capacity } else { data.len() * 2 }
+            } else { data.len() * 2 };
+        
+        let mut heap = Self::with_order(Some(capacity), branching_factor, order);
+        for pair in data {
+            if heap.contains(pair.get_element()) {
+                return Err(anyhow::Error::new(DHeapError::ElementAlreadyExists));
+            }
+            heap.push_unordered(pair.clone());
+        }
+        heap.heapify();
+            
+        Ok(heap)
+    }
+}
+
+impl<T: Eq + Hash, P: Ord> Extend<Pair<T, P>> for DHeap<T, P> {
+    /// Inserts the pairs, an element which is already in the heap gets the new priority
+    // when there are more new pairs than the existing ones, heapifying everything at once is cheaper
+    fn extend<I: IntoIterator<Item = Pair<T, P>>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (lower_bound, _) = iter.size_hint();
+        let bulk = lower_bound > self.data.len();
+        if bulk {
+            self.data.reserve(lower_bound);
+            self.hashes.reserve(lower_bound);
+        }
+
+        for pair in iter {
+            match self.find_index(pair.get_element()) {
+                Some(index) => {
+                    self.data[index].priority = pair.priority;
+                    if !bulk { self.sift(index); }
+                },
+                None => {
+                    self.push_unordered(pair);
+                    if !bulk { self.bubble_up(None); }
+                }
+            }
+        }
+
+        if bulk { self.heapify(); }
+    }
+}
+
+impl<T: Eq + Hash, P: Ord> FromIterator<Pair<T, P>> for DHeap<T, P> {
+    /// Creates a max-heap, a repeated element keeps the last priority
+    fn from_iter<I: IntoIterator<Item = Pair<T, P>>>(iter: I) -> Self {
+        let mut heap = DHeap::new(None, None);
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Eq + Hash, P: Ord> IntoIterator for DHeap<T, P> {
+    type Item = Pair<T, P>;
+    type IntoIter = std::vec::IntoIter<Pair<T, P>>;
+
+    /// Pairs come in no particular order, see into_sorted_vec for the ordered ones
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T: Eq + Hash, P: Ord> IntoIterator for &'a DHeap<T, P> {
+    type Item = &'a Pair<T, P>;
+    type IntoIter = std::slice::Iter<'a, Pair<T, P>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use float::TotalF64;
+    use std::cmp::Reverse;
+
     fn testing_dheap() -> DHeap<String> {
         let mut heap = DHeap::new(None, None);
         for i in 1..10 {
@@ -294,7 +412,7 @@ mod tests {
     #[test]
     fn update_is_correct() {
         let mut heap = testing_dheap();
-        _ = heap.update_priority("9".to_string(), 10);
+        heap.update_priority("9", 10);
         let top_pair = heap.top().unwrap();
         assert_eq!(10, top_pair.priority);
     }
@@ -322,4 +440,233 @@ mod tests {
         let heap = DHeap::with_pairs(&pairs, None, Some(4)).unwrap();
         assert!(heap.contains(&"11"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn max_priority_is_legal() {
+        let mut heap = DHeap::new(None, None);
+        heap.insert_value("max", usize::MAX).unwrap();
+        heap.insert_value("zero", 0).unwrap();
+        assert_eq!(usize::MAX, heap.remove("max").unwrap().priority);
+        assert_eq!(0, heap.top().unwrap().priority);
+        assert!(heap.top().is_none());
+    }
+
+    #[test]
+    fn remove_keeps_heap_order() {
+        let mut heap = testing_dheap();
+        assert_eq!(4, heap.remove("4").unwrap().priority);
+        assert!(heap.remove("4").is_none());
+        let priorities: Vec<usize> = std::iter::from_fn(|| heap.top()).map(|pair| pair.priority).collect();
+        assert_eq!(vec![9, 8, 7, 6, 5, 3, 2, 1], priorities);
+    }
+
+    #[test]
+    fn float_priorities() {
+        let mut heap = DHeap::new(None, Some(2));
+        heap.insert_value("slow", TotalF64(0.5)).unwrap();
+        heap.insert_value("urgent", TotalF64(12.75)).unwrap();
+        heap.insert_value("negative", TotalF64(-3.0)).unwrap();
+        assert_eq!("urgent", *heap.top().unwrap().get_element());
+        assert_eq!("slow", *heap.top().unwrap().get_element());
+    }
+
+    #[test]
+    fn tuple_priorities() {
+        let mut heap = DHeap::new(None, None);
+        heap.insert_value("b", (1, 5)).unwrap();
+        heap.insert_value("a", (2, 0)).unwrap();
+        heap.insert_value("c", (1, 9)).unwrap();
+        assert_eq!((2, 0), heap.top().unwrap().priority);
+        assert_eq!((1, 9), heap.top().unwrap().priority);
+    }
+
+    #[test]
+    fn duplicate_pairs_are_rejected() {
+        let pairs = vec![Pair::new("a", 1), Pair::new("a", 2)];
+        assert!(DHeap::with_pairs(&pairs, None, None).is_err());
+        assert!(DHeap::<&str>::with_pairs(&[], None, None).is_ok());
+    }
+
+    fn drain_priorities<P: Ord + Clone>(heap: &mut DHeap<String, P>) -> Vec<P> {
+        std::iter::from_fn(|| heap.top()).map(|pair| pair.priority).collect()
+    }
+
+    #[test]
+    fn min_heap_order() {
+        let mut heap = DHeap::with_order(None, Some(3), HeapOrder::Min);
+        for i in [5, 1, 9, 3, 7, 2] {
+            heap.insert_value(i.to_string(), i).unwrap();
+        }
+        heap.remove("3");
+        assert_eq!(vec![1, 2, 5, 7, 9], drain_priorities(&mut heap));
+    }
+
+    #[test]
+    fn min_heap_from_pairs() {
+        let pairs = vec![Pair::new("9".to_string(), 9), Pair::new("4".to_string(), 4), Pair::new("11".to_string(), 11)];
+        let mut heap = DHeap::with_pairs_and_order(&pairs, None, Some(2), HeapOrder::Min).unwrap();
+        assert_eq!(vec![4, 9, 11], drain_priorities(&mut heap));
+    }
+
+    #[test]
+    fn custom_comparator_order() {
+        // the closest to 10 is the highest
+        let mut heap = DHeap::with_order(None, None, HeapOrder::Custom(|a: &i32, b: &i32| (b - 10).abs().cmp(&(a - 10).abs())));
+        for i in [0, 30, 12, 7, 10] {
+            heap.insert_value(i.to_string(), i).unwrap();
+        }
+        assert_eq!(vec![10, 12, 7, 0, 30], drain_priorities(&mut heap));
+    }
+
+    #[test]
+    fn reverse_priorities() {
+        let mut heap = DHeap::new(None, None);
+        for i in [4, 8, 1] {
+            heap.insert_value(i.to_string(), Reverse(i)).unwrap();
+        }
+        assert_eq!(vec![Reverse(1), Reverse(4), Reverse(8)], drain_priorities(&mut heap));
+    }
+
+    #[test]
+    fn increase_and_decrease_priority() {
+        let mut heap = testing_dheap();
+        heap.increase_priority(&"2".to_string(), 20).unwrap();
+        heap.decrease_priority(&"9".to_string(), 0).unwrap();
+        assert!(heap.increase_priority(&"5".to_string(), 1).is_err());
+        assert!(heap.decrease_priority(&"5".to_string(), 6).is_err());
+        assert!(heap.increase_priority(&"missing".to_string(), 6).is_err());
+        assert_eq!(vec![20, 8, 7, 6, 5, 4, 3, 1, 0], drain_priorities(&mut heap));
+    }
+
+    // xorshift, good enough to shuffle the operations
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn assert_heap_is_valid<P: Ord + Clone>(heap: &DHeap<String, P>) {
+        assert_eq!(heap.data.len(), heap.hashes.len());
+        for (index, pair) in heap.data.iter().enumerate() {
+            assert_eq!(heap.slots.hash(pair.get_element()), heap.hashes[index]);
+            assert_eq!(Some(index), heap.find_index(pair.get_element()));
+            if index > 0 {
+                assert!(!heap.is_higher(index, heap.get_parent_index(index)));
+            }
+        }
+    }
+
+    #[test]
+    fn random_operations_keep_heap_invariant() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for branching_factor in [2, 3, 4, 7] {
+            let mut heap = DHeap::new(None, Some(branching_factor));
+            for _ in 0..2000 {
+                let element = (next_random(&mut state) % 64).to_string();
+                let priority = next_random(&mut state) % 1000;
+                match next_random(&mut state) % 5 {
+                    0 => { _ = heap.insert_value(element, priority); },
+                    1 => { heap.remove(&element); },
+                    2 => heap.update_priority(&element, priority),
+                    3 => { _ = heap.increase_priority(&element, priority); },
+                    _ => {
+                        let top = heap.top();
+                        assert!(heap.peek().is_none() || top.unwrap().priority >= heap.peek().unwrap().priority);
+                    },
+                }
+                assert_heap_is_valid(&heap);
+            }
+        }
+    }
+
+    #[test]
+    fn heapify_keeps_positions() {
+        let pairs: Vec<Pair<String, usize>> = (0..100).map(|i| Pair::new(i.to_string(), (i * 37) % 101 + 1)).collect();
+        let mut heap = DHeap::with_pairs_and_order(&pairs, None, Some(3), HeapOrder::Min).unwrap();
+        assert_heap_is_valid(&heap);
+        heap.decrease_priority(&"50".to_string(), 0).unwrap();
+        assert_heap_is_valid(&heap);
+        assert_eq!("50", heap.top().unwrap().get_element());
+    }
+
+    // neither Clone nor Display
+    #[derive(PartialEq, Eq, Hash, Debug)]
+    struct Job {
+        id: u32,
+        payload: Vec<u8>,
+    }
+
+    #[test]
+    fn elements_are_not_cloned() {
+        let mut heap = DHeap::with_order(None, Some(2), HeapOrder::Min);
+        for id in 0..20 {
+            heap.insert_value(Job { id, payload: vec![id as u8; 1024] }, 20 - id).unwrap();
+        }
+        assert!(heap.insert_value(Job { id: 3, payload: vec![3; 1024] }, 1).is_err());
+        heap.update_priority(&Job { id: 3, payload: vec![3; 1024] }, 0);
+        assert_eq!(20, heap.remove(&Job { id: 0, payload: vec![0; 1024] }).unwrap().priority);
+
+        let job = heap.top().unwrap().into_element();
+        assert_eq!(3, job.id);
+        assert_eq!(19, heap.top().unwrap().get_element().id);
+        assert_eq!(17, heap.len());
+    }
+
+    #[test]
+    fn borrowed_lookups() {
+        let mut heap = testing_dheap();
+        assert!(heap.contains("5"));
+        heap.increase_priority("5", 50).unwrap();
+        assert_eq!("5", heap.peek().unwrap().get_element());
+        assert!(heap.remove("5").is_some());
+        assert!(!heap.contains("5"));
+    }
+
+    #[test]
+    fn len_and_clear() {
+        let mut heap = testing_dheap();
+        assert_eq!(9, heap.len());
+        heap.top();
+        assert_eq!(8, heap.len());
+        heap.clear();
+        assert!(heap.is_empty());
+        assert!(heap.peek().is_none());
+        assert!(heap.insert_value("1".to_string(), 1).is_ok());
+    }
+
+    #[test]
+    fn collects_and_extends() {
+        let mut heap: DHeap<String> = (0..50).map(|i| Pair::new(i.to_string(), i)).collect();
+        assert_heap_is_valid(&heap);
+        // few pairs are inserted one by one, many are heapified at once
+        heap.extend(vec![Pair::new("7".to_string(), 500), Pair::new("x".to_string(), 60)]);
+        assert_heap_is_valid(&heap);
+        heap.extend((25..200).map(|i| Pair::new(i.to_string(), i)));
+        assert_heap_is_valid(&heap);
+
+        assert_eq!(201, heap.len());
+        let sorted = heap.into_sorted_vec();
+        assert_eq!(("7", 500), (sorted[0].get_element().as_str(), sorted[0].priority));
+        assert!(sorted.windows(2).all(|pairs| pairs[0].priority >= pairs[1].priority));
+    }
+
+    #[test]
+    fn iterates_over_all_pairs() {
+        let heap = testing_dheap();
+        let mut priorities: Vec<usize> = heap.iter().map(|pair| pair.priority).collect();
+        priorities.sort();
+        assert_eq!((1..10).collect::<Vec<usize>>(), priorities);
+        assert_eq!(45, (&heap).into_iter().map(|pair| pair.priority).sum::<usize>());
+        assert_eq!(9, heap.into_iter().count());
+    }
+
+    #[test]
+    fn drain_sorted_follows_heap_order() {
+        let mut heap = DHeap::with_order(None, None, HeapOrder::Min);
+        heap.extend([5, 3, 8, 1].map(|i| Pair::new(i.to_string(), i)));
+        let priorities: Vec<usize> = heap.drain_sorted().map(|pair| pair.priority).collect();
+        assert_eq!(vec![1, 3, 5, 8], priorities);
+        assert!(heap.is_empty());
+    }
+}
@@ -0,0 +1,44 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+/// Decides which priority is closer to the top of the heap
+/// Max puts the largest priority on the top, Min the smallest one
+/// Custom takes a comparator, the pair whose priority compares as Greater goes up.
+/// std::cmp::Reverse priorities with Max work the same as Min
+#[derive(Default)]
+pub enum HeapOrder<P> {
+    #[default]
+    Max,
+    Min,
+    Custom(fn(&P, &P) -> Ordering),
+}
+
+impl<P: Ord> HeapOrder<P> {
+    /// Returns true if the first priority should be above the second one
+    pub fn is_higher(&self, first: &P, second: &P) -> bool {
+        match self {
+            HeapOrder::Max => first > second,
+            HeapOrder::Min => first < second,
+            HeapOrder::Custom(compare) => compare(first, second) == Ordering::Greater,
+        }
+    }
+}
+
+// implemented by hand, deriving would require P to be Clone and Debug
+impl<P> Clone for HeapOrder<P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P> Copy for HeapOrder<P> {}
+
+impl<P> Debug for HeapOrder<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeapOrder::Max => write!(f, "Max"),
+            HeapOrder::Min => write!(f, "Min"),
+            HeapOrder::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
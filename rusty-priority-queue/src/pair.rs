@@ -1,27 +1,28 @@
 use std::fmt::Display;
 
 #[derive(Clone, Copy, Debug)]
-pub struct Pair<T> where T : Clone + Sized + Display + PartialEq {
-    pub priority: usize,
+pub struct Pair<T, P = usize> {
+    pub priority: P,
     element: T
 }
 
-impl<T> Pair<T> where T : Clone + Sized + Display + PartialEq {
-    pub fn new(element: T, priority: usize) -> Self {
+impl<T, P> Pair<T, P> {
+    pub fn new(element: T, priority: P) -> Self {
         Self { priority, element }
     }
 
-    pub(super) fn get_element(&self) -> &T {
+    pub fn get_element(&self) -> &T {
         &self.element
     }
 
-    pub(super) fn get_cloned_element(&self) -> T {
-        self.element.clone()
+    /// Takes the element out of the pair
+    pub fn into_element(self) -> T {
+        self.element
     }
 }
 
-impl<T: Clone + Display + PartialEq> Display for Pair<T> {
+impl<T: Display, P: Display> Display for Pair<T, P> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "priority: {}, element: {}", self.priority, self.element)
     }
-}
\ No newline at end of file
+}
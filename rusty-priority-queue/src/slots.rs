@@ -0,0 +1,111 @@
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+/// Points the hashes of the elements to their slots in the heap
+/// Elements are kept only in the heap, so the caller compares the elements on the slots of a hash
+#[derive(Debug, Default)]
+pub(crate) struct SlotIndex {
+    hasher: RandomState,
+    slots: HashMap<u64, Slots>,
+}
+
+#[derive(Debug)]
+enum Slots {
+    One(usize),
+    // different elements with the same hash
+    Many(Vec<usize>),
+}
+
+impl SlotIndex {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self { hasher: RandomState::new(), slots: HashMap::with_capacity(capacity) }
+    }
+
+    pub(crate) fn hash<Q: Hash + ?Sized>(&self, element: &Q) -> u64 {
+        self.hasher.hash_one(element)
+    }
+
+    /// Slots of the elements with the given hash
+    pub(crate) fn get(&self, hash: u64) -> &[usize] {
+        match self.slots.get(&hash) {
+            Some(Slots::One(slot)) => std::slice::from_ref(slot),
+            Some(Slots::Many(slots)) => slots,
+            None => &[],
+        }
+    }
+
+    pub(crate) fn insert(&mut self, hash: u64, slot: usize) {
+        match self.slots.entry(hash) {
+            Entry::Vacant(entry) => { entry.insert(Slots::One(slot)); },
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                Slots::One(existing) => {
+                    let existing = *existing;
+                    entry.insert(Slots::Many(vec![existing, slot]));
+                },
+                Slots::Many(slots) => slots.push(slot),
+            },
+        }
+    }
+
+    pub(crate) fn remove(&mut self, hash: u64, slot: usize) {
+        let Entry::Occupied(mut entry) = self.slots.entry(hash) else { return; };
+        let remaining = match entry.get_mut() {
+            Slots::One(existing) if *existing == slot => None,
+            Slots::One(_) => return,
+            Slots::Many(slots) => {
+                if let Some(position) = slots.iter().position(|&existing| existing == slot) {
+                    slots.swap_remove(position);
+                }
+                if slots.len() > 1 { return; }
+                slots.first().copied()
+            },
+        };
+        match remaining {
+            Some(last) => { entry.insert(Slots::One(last)); },
+            None => { entry.remove(); },
+        }
+    }
+
+    /// Called when the element with the hash moves to another slot
+    pub(crate) fn relocate(&mut self, hash: u64, from: usize, to: usize) {
+        match self.slots.get_mut(&hash) {
+            Some(Slots::One(slot)) if *slot == from => *slot = to,
+            Some(Slots::Many(slots)) => {
+                if let Some(slot) = slots.iter_mut().find(|slot| **slot == from) {
+                    *slot = to;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.slots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_slots_of_colliding_hashes() {
+        let mut index = SlotIndex::default();
+        index.insert(7, 0);
+        index.insert(7, 3);
+        index.insert(9, 1);
+        // swapping two elements with the same hash
+        index.relocate(7, 3, 0);
+        index.relocate(7, 0, 3);
+        let mut slots = index.get(7).to_vec();
+        slots.sort();
+        assert_eq!(vec![0, 3], slots);
+
+        index.remove(7, 0);
+        assert_eq!(&[3], index.get(7));
+        index.remove(7, 3);
+        assert!(index.get(7).is_empty());
+        assert_eq!(&[1], index.get(9));
+    }
+}